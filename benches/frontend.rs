@@ -0,0 +1,75 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use regula_project::{Globals, Lexer, Parser, Resolver};
+use std::fmt::Write;
+use std::hint::black_box;
+use std::rc::Rc;
+
+// A few thousand functions that mostly shuffle identifiers around, which is
+// where interning pays off.
+fn generate(funcs: usize) -> String {
+    let mut source = String::from("let mut counter = 0;\n");
+    for i in 0..funcs {
+        writeln!(
+            source,
+            "func compute_{i}(input_value: int, mut scale_factor: int) -> int {{
+    let mut running_total = input_value;
+    for let mut index = 0; index < 10; index += 1 {{
+        running_total += index * scale_factor;
+        scale_factor = scale_factor ^ (index << 1);
+    }}
+    let values = [running_total, input_value, scale_factor];
+    for value in values {{
+        counter += value & 255;
+    }}
+    if running_total > input_value {{ running_total - input_value }} else {{ compute_{j}(input_value, 1) }}
+}}",
+            j = i.saturating_sub(1),
+        )
+        .unwrap();
+    }
+    source
+}
+
+fn frontend(c: &mut Criterion) {
+    let source = generate(2000);
+    let input: Rc<[u8]> = Rc::from(source.as_bytes());
+
+    let mut group = c.benchmark_group("frontend");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(20);
+
+    group.bench_function("lex", |b| {
+        b.iter(|| {
+            let mut lexer = Lexer::new(String::from("<bench>"), black_box(&input));
+            lexer.tokenize_all().unwrap()
+        })
+    });
+
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            let lexer = Lexer::new(String::from("<bench>"), black_box(&input));
+            Parser::from_lexer(lexer, &input, String::from("<bench>"))
+                .parse()
+                .unwrap()
+        })
+    });
+
+    group.bench_function("resolve", |b| {
+        b.iter(|| {
+            let lexer = Lexer::new(String::from("<bench>"), black_box(&input));
+            let mut program = Parser::from_lexer(lexer, &input, String::from("<bench>"))
+                .parse()
+                .unwrap();
+            let mut globals = Globals::new();
+            Resolver::new(&mut globals, &input, String::from("<bench>"))
+                .resolve(&mut program)
+                .unwrap();
+            program
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, frontend);
+criterion_main!(benches);
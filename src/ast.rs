@@ -1,4 +1,90 @@
 use crate::span::Span;
+use crate::symbol::Symbol;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+// Identifies a node in its program's `Ast`, so passes can keep per-node data
+// in a `NodeMap` instead of on the node itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    #[inline(always)]
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+// Owns every node of a program; children are referred to by id.
+#[derive(Debug, Default)]
+pub struct Ast {
+    nodes: Vec<Expr>,
+}
+
+impl Ast {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline(always)]
+    pub fn alloc(&mut self, expr: Expr) -> NodeId {
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(expr);
+        id
+    }
+
+    #[inline(always)]
+    pub fn span(&self, id: NodeId) -> Span {
+        *self[id].span()
+    }
+}
+
+impl Index<NodeId> for Ast {
+    type Output = Expr;
+
+    #[inline(always)]
+    fn index(&self, id: NodeId) -> &Expr {
+        &self.nodes[id.index()]
+    }
+}
+
+impl IndexMut<NodeId> for Ast {
+    #[inline(always)]
+    fn index_mut(&mut self, id: NodeId) -> &mut Expr {
+        &mut self.nodes[id.index()]
+    }
+}
+
+// Data attached to nodes by a pass, such as resolved slots or inferred types.
+#[derive(Debug, Clone)]
+pub struct NodeMap<T> {
+    values: Vec<Option<T>>,
+}
+
+impl<T> Default for NodeMap<T> {
+    fn default() -> Self {
+        Self { values: Vec::new() }
+    }
+}
+
+impl<T> NodeMap<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: NodeId, value: T) {
+        let index = id.index();
+        if index >= self.values.len() {
+            self.values.resize_with(index + 1, || None);
+        }
+        self.values[index] = Some(value);
+    }
+
+    #[inline(always)]
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.values.get(id.index()).and_then(Option::as_ref)
+    }
+}
 
 // Everything is an expression.
 #[derive(Debug, Clone, PartialEq)]
@@ -8,68 +94,249 @@ pub enum Expr {
         span: Span,
     },
 
+    // The resolver records the slot of every `Ident` and `Func` in `Program::slots`.
     Ident {
-        name: String,
+        name: Symbol,
         span: Span,
     },
+
+    Array {
+        elements: Vec<NodeId>,
+        span: Span,
+    },
+
+    // `f"x = {x}"`: text segments are string literals, in order with the
+    // embedded expressions.
+    Format {
+        parts: Vec<NodeId>,
+        span: Span,
+    },
+
     Binary {
         op: BinOp,
-        left: Box<Expr>,
-        right: Box<Expr>,
+        left: NodeId,
+        right: NodeId,
         span: Span,
     },
 
     Unary {
         op: UnaryOp,
-        expr: Box<Expr>,
+        expr: NodeId,
+        span: Span,
+    },
+
+    Index {
+        target: NodeId,
+        index: NodeId,
+        span: Span,
+    },
+
+    Field {
+        target: NodeId,
+        name: Symbol,
         span: Span,
     },
 
+    // `op` is `Some` for compound assignments such as `a += 1`.
     Assign {
-        target: Box<Expr>,
-        value: Box<Expr>,
+        op: Option<BinOp>,
+        target: NodeId,
+        value: NodeId,
         span: Span,
     },
 
+    // `tail` is set when the last expression is not followed by `;`, making
+    // its value the value of the whole block.
     Block {
-        exprs: Vec<Expr>,
+        exprs: Vec<NodeId>,
+        tail: bool,
+        span: Span,
+    },
+
+    If {
+        cond: NodeId,
+        then_branch: NodeId,
+        else_branch: Option<NodeId>,
+        span: Span,
+    },
+
+    While {
+        cond: NodeId,
+        body: NodeId,
+        span: Span,
+    },
+
+    Loop {
+        body: NodeId,
+        span: Span,
+    },
+
+    For {
+        init: Option<NodeId>,
+        cond: Option<NodeId>,
+        step: Option<NodeId>,
+        body: NodeId,
+        span: Span,
+    },
+
+    ForIn {
+        var: NodeId,
+        iter: NodeId,
+        body: NodeId,
+        span: Span,
+    },
+
+    Break {
+        value: Option<NodeId>,
+        span: Span,
+    },
+
+    Continue {
+        span: Span,
+    },
+
+    // `doc` holds the text of the `///` comments in front of a declaration.
+    Func {
+        doc: Option<String>,
+        name: Symbol,
+        name_span: Span,
+        params: Vec<Param>,
+        ret: Type,
+        ret_span: Option<Span>,
+        body: NodeId,
+        span: Span,
+    },
+
+    Call {
+        callee: NodeId,
+        args: Vec<NodeId>,
+        span: Span,
+    },
+
+    Return {
+        value: Option<NodeId>,
         span: Span,
     },
 
     Let {
-        target: Box<Expr>,
+        doc: Option<String>,
+        target: NodeId,
+        mutable: bool,
         kind: Type,
-        init: Option<Box<Expr>>,
+        kind_span: Option<Span>,
+        init: Option<NodeId>,
         span: Span,
     },
 
     Const {
-        target: Box<Expr>,
+        doc: Option<String>,
+        target: NodeId,
         kind: Type,
-        value: Box<Expr>,
+        kind_span: Span,
+        value: NodeId,
         span: Span,
     },
 }
 
+// Every kind of expression with its child fields in source order. Invokes
+// `$m` with the whole list, so that `Expr::span` and the traversals in `visit`
+// are generated from this one place and stop compiling when a variant is
+// missing here.
+macro_rules! expr_children {
+    ($m:ident) => {
+        $m! {
+            Literal {},
+            Ident {},
+            Array { elements },
+            Format { parts },
+            Binary { left, right },
+            Unary { expr },
+            Index { target, index },
+            Field { target },
+            Assign { target, value },
+            Block { exprs },
+            If { cond, then_branch, else_branch },
+            While { cond, body },
+            Loop { body },
+            For { init, cond, step, body },
+            ForIn { var, iter, body },
+            Break { value },
+            Continue {},
+            Func { body },
+            Call { callee, args },
+            Return { value },
+            Let { target, init },
+            Const { target, value },
+        }
+    };
+}
+
+macro_rules! expr_span {
+    ($($variant:ident { $($field:ident),* }),* $(,)?) => {
+        impl Expr {
+            #[inline(always)]
+            pub fn span(&self) -> &Span {
+                match self {
+                    $(Expr::$variant { span, .. } => span,)*
+                }
+            }
+        }
+    };
+}
+
+expr_children!(expr_span);
+
 impl Expr {
     #[inline(always)]
-    pub fn span(&self) -> &Span {
-        match self {
-            Expr::Literal { span, .. } => span,
-            Expr::Ident { span, .. } => span,
-            Expr::Binary { span, .. } => span,
-            Expr::Unary { span, .. } => span,
-            Expr::Assign { span, .. } => span,
-            Expr::Block { span, .. } => span,
-            Expr::Let { span, .. } => span,
-            Expr::Const { span, .. } => span,
-        }
+    pub fn is_block_like(&self) -> bool {
+        matches!(
+            self,
+            Expr::Block { .. }
+                | Expr::If { .. }
+                | Expr::While { .. }
+                | Expr::Loop { .. }
+                | Expr::For { .. }
+                | Expr::ForIn { .. }
+                | Expr::Func { .. }
+        )
+    }
+
+    #[inline(always)]
+    pub fn is_place(&self) -> bool {
+        matches!(
+            self,
+            Expr::Ident { .. } | Expr::Index { .. } | Expr::Field { .. }
+        )
     }
 }
 
+// Where a binding lives at runtime. Locals are counted in scopes outwards from
+// the innermost one, and never cross a function boundary. A function declared
+// in an enclosing function is referred to by its declaration instead; bodies
+// capture nothing, so it needs no frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slot {
+    Global(usize),
+    Local { depth: usize, index: usize },
+    Func(NodeId),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Symbol,
+    pub name_span: Span,
+    pub mutable: bool,
+    pub kind: Type,
+    pub span: Span,
+}
+
 #[derive(Debug)]
 pub struct Program {
-    pub body: Expr,
+    // Text of the `//!` comments at the top of the file.
+    pub doc: Option<String>,
+    pub ast: Ast,
+    pub body: NodeId,
+    pub slots: NodeMap<Slot>,
     pub filename: String,
     pub span: Span,
 }
@@ -79,6 +346,7 @@ pub enum Literal {
     Int(i64),
     Float(f64),
     String(String),
+    Char(char),
     Bool(bool),
     None,
 }
@@ -92,15 +360,18 @@ pub enum BinOp {
     Mod,
     Pow,
 
-    Eq,
-    Lt,
-    Gt,
-    Lte,
-    Gte,
-    LtE,
-    GtE,
-    EqE,
-    Neq,
+    Eq,  // ==
+    Neq, // !=
+    Lt,  // <
+    Gt,  // >
+    Lte, // <=
+    Gte, // >=
+
+    BitAnd, // &
+    BitOr,  // |
+    BitXor, // ^
+    Shl,    // <<
+    Shr,    // >>
 
     And,
     Or,
@@ -108,11 +379,11 @@ pub enum BinOp {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum UnaryOp {
-    Neg, // -
-    Not, // !
-
-         // Pos // + (unary plus) -Omitted, as it is semantically redundant (+x = x),
-         // and has no effect on the expression value
+    // Pos // + (unary plus) -Omitted, as it is semantically redundant (+x = x),
+    // and has no effect on the expression value
+    Neg,    // -
+    Not,    // !
+    BitNot, // ~
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -122,5 +393,72 @@ pub enum Type {
     Float,
     Bool,
     String,
+    Char,
     None,
+    Array(Box<Type>),
+    Func { params: Vec<Type>, ret: Box<Type> },
+    Never, // type of `return`, `break` and `continue`, which never produce a value
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Auto => write!(f, "_"),
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "str"),
+            Type::Char => write!(f, "char"),
+            Type::None => write!(f, "None"),
+            Type::Array(elem) => write!(f, "[{}]", elem),
+            Type::Func { params, ret } => {
+                write!(f, "func(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ") -> {}", ret)
+            }
+            Type::Never => write!(f, "!"),
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Pow => "**",
+            BinOp::Eq => "==",
+            BinOp::Neq => "!=",
+            BinOp::Lt => "<",
+            BinOp::Gt => ">",
+            BinOp::Lte => "<=",
+            BinOp::Gte => ">=",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOp::Neg => write!(f, "-"),
+            UnaryOp::Not => write!(f, "!"),
+            UnaryOp::BitNot => write!(f, "~"),
+        }
+    }
 }
@@ -1,16 +1,27 @@
 use crate::Span;
+use crate::ast::{BinOp, Type, UnaryOp};
 use std::error::Error;
 use std::fmt;
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub enum ErrorKind {
     UnclosedString,
+    InvalidEscape,
+    EmptyChar,
+    MultipleChars,
     UnexpectedCharacter,
     UnclosedParenthesis,
+    UnclosedBracket,
+    UnclosedBrace,
+    UnclosedComment,
+    ExpectedBlock,
     MissingSemicolon,
     InvalidSyntax,
+    DanglingDocComment,
     ExpectedInteger,
     ExpectedFloat,
+    InvalidNumber,
     ExpectedIdentifier,
     ExpectedString,
     ExpectedBoolean,
@@ -18,30 +29,106 @@ pub enum ErrorKind {
     ExpectedType,
     MissingTypeAnnotation,
     MissingAssignment,
+    UndefinedVariable,
+    UseBeforeDeclaration,
+    CapturedLocal,
+    DuplicateDefinition,
+    ShadowedConst,
+    ImmutableAssignment,
+    InvalidOperand,
+    DivisionByZero,
+    IntegerOverflow,
+    FloatOverflow,
+    ChainedComparison,
+    InvalidAssignmentTarget,
+    ReturnOutsideFunction,
+    NotCallable,
+    ArgumentCountMismatch,
+    MissingElse,
+    OutsideLoop,
+    BreakWithValue,
+    IndexOutOfBounds,
+    // Types are boxed to keep `CompilerError` small on the hot `Ok` path.
+    TypeMismatch {
+        expected: Box<Type>,
+        found: Box<Type>,
+    },
+    BinaryOperandTypes {
+        op: BinOp,
+        left: Box<Type>,
+        right: Box<Type>,
+    },
+    UnaryOperandType {
+        op: UnaryOp,
+        operand: Box<Type>,
+    },
+    UnknownField {
+        ty: Box<Type>,
+    },
+    NotDisplayable {
+        ty: Box<Type>,
+    },
+    // Stands in for the errors past `MAX_ERRORS`.
+    TooManyErrors {
+        omitted: usize,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub struct CompilerError {
     pub kind: ErrorKind,
     pub span: Span,
-    pub input: Vec<u8>,
+    // Shared with every other error from the same source.
+    pub input: Rc<[u8]>,
     pub filename: String,
+    // A secondary location that explains the error, e.g. a type annotation.
+    pub note: Option<(Span, &'static str)>,
 }
 
 impl CompilerError {
     #[cold]
-    pub fn new(kind: ErrorKind, span: Span, input: &[u8], filename: String) -> Self {
+    pub fn new(kind: ErrorKind, span: Span, input: &Rc<[u8]>, filename: String) -> Self {
         Self {
             kind,
             span,
-            input: input.to_vec(),
+            input: input.clone(),
             filename,
+            note: None,
         }
     }
+
+    #[cold]
+    pub fn with_note(mut self, span: Span, note: &'static str) -> Self {
+        self.note = Some((span, note));
+        self
+    }
+}
+
+// Past this many errors the rest are only counted, so a garbled file does not
+// bury the first and most useful ones.
+pub const MAX_ERRORS: usize = 100;
+
+#[cold]
+pub fn push_error(errors: &mut Vec<CompilerError>, error: CompilerError) {
+    if errors.len() < MAX_ERRORS {
+        errors.push(error);
+    } else if let Some(CompilerError {
+        kind: ErrorKind::TooManyErrors { omitted },
+        ..
+    }) = errors.last_mut()
+    {
+        *omitted += 1;
+    } else {
+        errors.push(CompilerError {
+            kind: ErrorKind::TooManyErrors { omitted: 1 },
+            note: None,
+            ..error
+        });
+    }
 }
 
 #[cold]
-fn line_col(input: &Vec<u8>, pos: usize) -> (usize, usize) {
+fn line_col(input: &[u8], pos: usize) -> (usize, usize) {
     let mut line = 1;
     let mut col = 1;
     for &c in &input[..pos] {
@@ -57,26 +144,48 @@ fn line_col(input: &Vec<u8>, pos: usize) -> (usize, usize) {
 }
 
 #[cold]
-fn snippet(input: &Vec<u8>, span: Span) -> String {
+fn snippet(input: &[u8], span: Span) -> String {
     String::from_utf8_lossy(&input[span.start..span.end]).to_string()
 }
 #[cold]
-fn format(kind: &ErrorKind, span: Span, input: &Vec<u8>, filename: &String) -> String {
+fn format(kind: &ErrorKind, span: Span, input: &[u8], filename: &str) -> String {
     let (start_line, start_col) = line_col(input, span.start);
     let (end_line, end_col) = line_col(input, span.end);
     let msg = message(kind, span, input);
     format!(
-        "{}:{}~{}:{}~{} {:?}: {}",
+        "{}:{}~{}:{}~{} {}: {}",
         filename, start_line, end_line, start_col, end_col, kind, msg
     )
 }
 
 #[cold]
-fn message(kind: &ErrorKind, span: Span, input: &Vec<u8>) -> String {
+fn format_note(note: &str, span: Span, input: &[u8], filename: &str) -> String {
+    let (start_line, start_col) = line_col(input, span.start);
+    let (end_line, end_col) = line_col(input, span.end);
+    format!(
+        "{}:{}~{}:{}~{} note: {} `{}`",
+        filename,
+        start_line,
+        end_line,
+        start_col,
+        end_col,
+        note,
+        snippet(input, span)
+    )
+}
+
+#[cold]
+fn message(kind: &ErrorKind, span: Span, input: &[u8]) -> String {
     let s = snippet(input, span);
 
     match kind {
         ErrorKind::UnclosedString => format!("unclosed string literal `{}`", s,),
+        ErrorKind::InvalidEscape => format!("invalid escape sequence `{}`", s),
+        ErrorKind::EmptyChar => format!("empty character literal `{}`", s),
+        ErrorKind::MultipleChars => format!(
+            "character literal may only contain one character, but found `{}` (use `\"` for a string)",
+            s
+        ),
         ErrorKind::UnexpectedCharacter => {
             let b = if span.start < input.len() {
                 input[span.start] as usize
@@ -88,28 +197,101 @@ fn message(kind: &ErrorKind, span: Span, input: &Vec<u8>) -> String {
         ErrorKind::UnclosedParenthesis => {
             String::from("unclosed parenthesis (expected `)` to close this `(`")
         }
+        ErrorKind::UnclosedBracket => {
+            String::from("unclosed bracket (expected `]` to close this `[`")
+        }
+        ErrorKind::UnclosedBrace => String::from("unclosed brace (expected `}` to close this `{`"),
+        ErrorKind::UnclosedComment => {
+            String::from("unclosed block comment (expected `*/` to close this `/*`")
+        }
+        ErrorKind::ExpectedBlock => format!("expected a block `{{ ... }}`, but found `{}`", s),
         ErrorKind::MissingSemicolon => {
             String::from("missing semicolon (please add a `;` after the expression)")
         }
         ErrorKind::InvalidSyntax => format!("invalid syntax `{}`", s),
+        ErrorKind::DanglingDocComment => {
+            String::from("doc comment does not document a declaration")
+        }
         ErrorKind::ExpectedInteger => format!("expected an integer, but found `{}`", s),
         ErrorKind::ExpectedFloat => format!("expected a float, but found `{}`", s),
+        ErrorKind::InvalidNumber => format!("invalid number literal `{}`", s),
         ErrorKind::ExpectedIdentifier => format!("expected an identifier, but found `{}`", s),
         ErrorKind::ExpectedString => format!("expected a string, but found `{}`", s),
         ErrorKind::ExpectedBoolean => format!("expected a boolean, but found `{}`", s),
         ErrorKind::ExpectedNone => format!("expected a None, but found `{}`", s),
         ErrorKind::ExpectedType => format!("expected a type after `:`, but found `{}`", s),
         ErrorKind::MissingTypeAnnotation => {
-            format!("const need a type annotation, but found `{}`", s)
+            format!("need a type annotation, but found `{}`", s)
         }
         ErrorKind::MissingAssignment => format!("need a assignment, but found `{}`", s),
+        ErrorKind::UndefinedVariable => format!("cannot find variable `{}` in this scope", s),
+        ErrorKind::UseBeforeDeclaration => format!("`{}` is used before its declaration", s),
+        ErrorKind::CapturedLocal => {
+            format!("cannot capture local `{}` of an enclosing function", s)
+        }
+        ErrorKind::DuplicateDefinition => {
+            format!("`{}` is defined more than once in this scope", s)
+        }
+        ErrorKind::ShadowedConst => format!("`{}` cannot shadow a constant", s),
+        ErrorKind::ImmutableAssignment => {
+            format!("cannot assign to an immutable binding in `{}`", s)
+        }
+        ErrorKind::InvalidOperand => format!("invalid operand types in `{}`", s),
+        ErrorKind::DivisionByZero => format!("attempt to divide by zero in `{}`", s),
+        ErrorKind::IntegerOverflow => format!("integer overflow in `{}`", s),
+        ErrorKind::FloatOverflow => format!("float literal `{}` is out of range", s),
+        ErrorKind::ChainedComparison => format!(
+            "comparison operators cannot be chained, but found `{}` (use `&&` to combine comparisons)",
+            s
+        ),
+        ErrorKind::InvalidAssignmentTarget => {
+            format!("invalid left-hand side of assignment `{}`", s)
+        }
+        ErrorKind::ReturnOutsideFunction => String::from("`return` outside of a function"),
+        ErrorKind::NotCallable => format!("`{}` is not a function", s),
+        ErrorKind::ArgumentCountMismatch => {
+            format!("wrong number of arguments in call `{}`", s)
+        }
+        ErrorKind::MissingElse => String::from("`if` used as a value must have an `else` branch"),
+        ErrorKind::OutsideLoop => format!("`{}` outside of a loop", s),
+        ErrorKind::BreakWithValue => {
+            format!(
+                "`break` with a value is only allowed inside `loop`, but found `{}`",
+                s
+            )
+        }
+        ErrorKind::IndexOutOfBounds => format!("index out of bounds in `{}`", s),
+        ErrorKind::TypeMismatch { expected, found } => format!(
+            "mismatched types: expected `{}`, found `{}` in `{}`",
+            expected, found, s
+        ),
+        ErrorKind::BinaryOperandTypes { op, left, right } => format!(
+            "cannot apply `{}` to `{}` and `{}` in `{}`",
+            op, left, right, s
+        ),
+        ErrorKind::UnaryOperandType { op, operand } => {
+            format!("cannot apply `{}` to `{}` in `{}`", op, operand, s)
+        }
+        ErrorKind::UnknownField { ty } => format!("no field `{}` on type `{}`", s, ty),
+        ErrorKind::NotDisplayable { ty } => {
+            format!("`{}` of type `{}` cannot be formatted", s, ty)
+        }
+        ErrorKind::TooManyErrors { omitted } => format!("{} more errors", omitted),
     }
 }
 
 impl fmt::Display for ErrorKind {
     #[cold]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            ErrorKind::TypeMismatch { .. } => write!(f, "TypeMismatch"),
+            ErrorKind::BinaryOperandTypes { .. } => write!(f, "BinaryOperandTypes"),
+            ErrorKind::UnaryOperandType { .. } => write!(f, "UnaryOperandType"),
+            ErrorKind::UnknownField { .. } => write!(f, "UnknownField"),
+            ErrorKind::NotDisplayable { .. } => write!(f, "NotDisplayable"),
+            ErrorKind::TooManyErrors { .. } => write!(f, "TooManyErrors"),
+            _ => write!(f, "{:?}", self),
+        }
     }
 }
 
@@ -122,7 +304,17 @@ impl fmt::Display for CompilerError {
             f,
             "{}",
             format(&self.kind, self.span, &self.input, &self.filename)
-        )
+        )?;
+
+        if let Some((span, note)) = self.note {
+            write!(
+                f,
+                "\n{}",
+                format_note(note, span, &self.input, &self.filename)
+            )?;
+        }
+
+        Ok(())
     }
 }
 
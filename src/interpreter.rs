@@ -0,0 +1,731 @@
+use crate::Span;
+use crate::ast::{BinOp, Expr, Literal, NodeId, Program, Slot, UnaryOp};
+use crate::error::{CompilerError, ErrorKind};
+use crate::value::{Function, Value};
+use branches::unlikely;
+use std::fmt::Write;
+use std::rc::Rc;
+
+// Non-local control flow travels up the evaluator through the error channel.
+enum Unwind {
+    Error(CompilerError),
+    Return(Value),
+    Break(Value),
+    Continue,
+}
+
+impl From<CompilerError> for Unwind {
+    #[inline(always)]
+    fn from(error: CompilerError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+type EvalResult = Result<Value, Unwind>;
+
+#[derive(Debug)]
+pub struct Environment {
+    globals: Vec<Value>,
+    // Local scopes of the running function, innermost last.
+    scopes: Vec<Vec<Value>>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self {
+            globals: Vec::new(),
+            scopes: Vec::new(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    #[inline(always)]
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    // Bindings are always declared in the innermost scope, but not necessarily
+    // in slot order, so a scope grows on demand.
+    pub fn define(&mut self, slot: Slot, value: Value) {
+        let (values, index) = match slot {
+            Slot::Global(index) => (&mut self.globals, index),
+            Slot::Local { index, .. } => (self.scopes.last_mut().unwrap(), index),
+            Slot::Func(_) => unreachable!("functions are defined in their own scope"),
+        };
+        if index >= values.len() {
+            values.resize(index + 1, Value::None);
+        }
+        values[index] = value;
+    }
+
+    pub fn get(&self, slot: Slot) -> Option<&Value> {
+        match slot {
+            Slot::Global(index) => self.globals.get(index),
+            Slot::Local { depth, index } => {
+                let scope = self.scopes.len().checked_sub(depth + 1)?;
+                self.scopes[scope].get(index)
+            }
+            Slot::Func(_) => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, slot: Slot) -> Option<&mut Value> {
+        match slot {
+            Slot::Global(index) => self.globals.get_mut(index),
+            Slot::Local { depth, index } => {
+                let scope = self.scopes.len().checked_sub(depth + 1)?;
+                self.scopes[scope].get_mut(index)
+            }
+            Slot::Func(_) => None,
+        }
+    }
+
+    // Function bodies only see the globals plus their own parameters; functions
+    // of enclosing bodies are rebuilt from their declaration.
+    fn enter_call(&mut self, args: Vec<Value>) -> Vec<Vec<Value>> {
+        std::mem::replace(&mut self.scopes, vec![args])
+    }
+
+    fn exit_call(&mut self, saved: Vec<Vec<Value>>) {
+        self.scopes = saved;
+    }
+}
+
+pub struct Interpreter<'a> {
+    env: &'a mut Environment,
+    input: &'a Rc<[u8]>,
+    filename: String,
+}
+
+impl<'a> Interpreter<'a> {
+    #[inline(always)]
+    pub fn new(env: &'a mut Environment, input: &'a Rc<[u8]>, filename: String) -> Self {
+        Self {
+            env,
+            input,
+            filename,
+        }
+    }
+
+    pub fn run(&mut self, program: &Rc<Program>) -> Result<Value, CompilerError> {
+        // The program body shares the global scope so the REPL keeps its bindings.
+        let result = match &program.ast[program.body] {
+            Expr::Block { exprs, .. } => self.eval_sequence(program, exprs),
+            _ => self.eval(program, program.body),
+        };
+
+        finish(result)
+    }
+
+    // Calls the function in global `slot`, typically `main`, if there is one.
+    pub fn call_global(&mut self, slot: usize) -> Option<Result<Value, CompilerError>> {
+        let func = match self.env.get(Slot::Global(slot)) {
+            Some(Value::Function(func)) => func.clone(),
+            _ => return None,
+        };
+
+        let span = func.span;
+        Some(self.call_function(&func, Vec::new(), span))
+    }
+
+    fn eval(&mut self, program: &Rc<Program>, id: NodeId) -> EvalResult {
+        match &program.ast[id] {
+            Expr::Literal { lit, .. } => Ok(self.eval_literal(lit)),
+            Expr::Ident { span, .. } => match program.slots.get(id) {
+                Some(Slot::Func(decl)) => match function(program, *decl) {
+                    Some(func) => Ok(Value::Function(Rc::new(func))),
+                    None => Err(self.error(ErrorKind::UndefinedVariable, *span).into()),
+                },
+                Some(slot) => match self.env.get(*slot) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(self.error(ErrorKind::UndefinedVariable, *span).into()),
+                },
+                None => Err(self.error(ErrorKind::UndefinedVariable, *span).into()),
+            },
+            Expr::Binary {
+                op,
+                left,
+                right,
+                span,
+            } => self.eval_binary(program, op, *left, *right, *span),
+            Expr::Unary { op, expr, span } => self.eval_unary(program, op, *expr, *span),
+            Expr::Array { elements, .. } => {
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.eval(program, *element)?);
+                }
+
+                Ok(Value::Array(Rc::new(values)))
+            }
+            Expr::Format { parts, .. } => {
+                let mut text = String::new();
+                for part in parts {
+                    let value = self.eval(program, *part)?;
+                    write!(text, "{}", value).unwrap();
+                }
+
+                Ok(Value::String(text))
+            }
+            Expr::Index {
+                target,
+                index,
+                span,
+            } => {
+                let target = self.eval(program, *target)?;
+                let index = self.eval(program, *index)?;
+                match element(&target, &index) {
+                    Ok(value) => Ok(value.clone()),
+                    Err(kind) => Err(self.error(kind, *span).into()),
+                }
+            }
+            Expr::Field { target, span, .. } => {
+                self.eval(program, *target)?;
+                Err(self.error(ErrorKind::InvalidOperand, *span).into())
+            }
+            Expr::Assign {
+                op,
+                target,
+                value,
+                span,
+            } => {
+                let value = self.eval(program, *value)?;
+                self.assign(program, *target, op.as_ref(), value, *span)?;
+                Ok(Value::None)
+            }
+            Expr::Block { exprs, tail, .. } => {
+                self.env.push_scope();
+                let result = self.eval_sequence(program, exprs);
+                self.env.pop_scope();
+                if *tail {
+                    result
+                } else {
+                    result.map(|_| Value::None)
+                }
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                if self.eval_cond(program, *cond)? {
+                    self.eval(program, *then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.eval(program, *else_branch)
+                } else {
+                    Ok(Value::None)
+                }
+            }
+            Expr::While { cond, body, .. } => {
+                while self.eval_cond(program, *cond)? {
+                    if self.eval_loop_body(program, *body)?.is_some() {
+                        break;
+                    }
+                }
+
+                Ok(Value::None)
+            }
+            Expr::Loop { body, .. } => loop {
+                if let Some(value) = self.eval_loop_body(program, *body)? {
+                    return Ok(value);
+                }
+            },
+            Expr::For {
+                init,
+                cond,
+                step,
+                body,
+                ..
+            } => {
+                self.env.push_scope();
+                let result = self.eval_for(program, [*init, *cond, *step], *body);
+                self.env.pop_scope();
+                result
+            }
+            Expr::ForIn {
+                var, iter, body, ..
+            } => {
+                let items = match self.eval(program, *iter)? {
+                    Value::Array(items) => items,
+                    Value::String(s) => Rc::new(s.chars().map(Value::Char).collect()),
+                    _ => {
+                        return Err(self
+                            .error(ErrorKind::InvalidOperand, program.ast.span(*iter))
+                            .into());
+                    }
+                };
+                let slot = self.target_slot(program, *var)?;
+
+                for item in items.iter() {
+                    self.env.push_scope();
+                    self.env.define(slot, item.clone());
+                    let result = self.eval_loop_body(program, *body);
+                    self.env.pop_scope();
+                    if result?.is_some() {
+                        break;
+                    }
+                }
+
+                Ok(Value::None)
+            }
+            Expr::Break { value, .. } => {
+                let value = match value {
+                    Some(value) => self.eval(program, *value)?,
+                    None => Value::None,
+                };
+                Err(Unwind::Break(value))
+            }
+            Expr::Continue { .. } => Err(Unwind::Continue),
+            Expr::Func { span, .. } => {
+                match (program.slots.get(id), function(program, id)) {
+                    (Some(slot), Some(func)) => {
+                        self.env.define(*slot, Value::Function(Rc::new(func)))
+                    }
+                    _ => return Err(self.error(ErrorKind::UndefinedVariable, *span).into()),
+                }
+                Ok(Value::None)
+            }
+            Expr::Call { callee, args, span } => {
+                let callee_value = self.eval(program, *callee)?;
+                let func = match callee_value {
+                    Value::Function(func) => func,
+                    _ => {
+                        return Err(self
+                            .error(ErrorKind::NotCallable, program.ast.span(*callee))
+                            .into());
+                    }
+                };
+
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(program, *arg)?);
+                }
+
+                Ok(self.call_function(&func, values, *span)?)
+            }
+            Expr::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.eval(program, *value)?,
+                    None => Value::None,
+                };
+                Err(Unwind::Return(value))
+            }
+            Expr::Let { target, init, .. } => {
+                let value = match init {
+                    Some(init) => self.eval(program, *init)?,
+                    None => Value::None,
+                };
+                let slot = self.target_slot(program, *target)?;
+                self.env.define(slot, value);
+                Ok(Value::None)
+            }
+            Expr::Const { target, value, .. } => {
+                let value = self.eval(program, *value)?;
+                let slot = self.target_slot(program, *target)?;
+                self.env.define(slot, value);
+                Ok(Value::None)
+            }
+        }
+    }
+
+    fn eval_cond(&mut self, program: &Rc<Program>, cond: NodeId) -> Result<bool, Unwind> {
+        match self.eval(program, cond)? {
+            Value::Bool(v) => Ok(v),
+            _ => Err(self
+                .error(ErrorKind::InvalidOperand, program.ast.span(cond))
+                .into()),
+        }
+    }
+
+    // Runs one iteration of a loop body; `Some` carries the value of a `break`.
+    fn eval_loop_body(
+        &mut self,
+        program: &Rc<Program>,
+        body: NodeId,
+    ) -> Result<Option<Value>, Unwind> {
+        match self.eval(program, body) {
+            Ok(_) | Err(Unwind::Continue) => Ok(None),
+            Err(Unwind::Break(value)) => Ok(Some(value)),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn eval_for(
+        &mut self,
+        program: &Rc<Program>,
+        [init, cond, step]: [Option<NodeId>; 3],
+        body: NodeId,
+    ) -> EvalResult {
+        if let Some(init) = init {
+            self.eval(program, init)?;
+        }
+
+        loop {
+            if let Some(cond) = cond
+                && !self.eval_cond(program, cond)?
+            {
+                break;
+            }
+            if self.eval_loop_body(program, body)?.is_some() {
+                break;
+            }
+            if let Some(step) = step {
+                self.eval(program, step)?;
+            }
+        }
+
+        Ok(Value::None)
+    }
+
+    fn assign(
+        &mut self,
+        program: &Rc<Program>,
+        target: NodeId,
+        op: Option<&BinOp>,
+        value: Value,
+        span: Span,
+    ) -> Result<(), Unwind> {
+        // Evaluate every index of the place before borrowing its slot mutably.
+        let mut indices = Vec::new();
+        let mut base = target;
+        while let Expr::Index {
+            target,
+            index,
+            span,
+        } = &program.ast[base]
+        {
+            indices.push((self.eval(program, *index)?, *span));
+            base = *target;
+        }
+        indices.reverse();
+
+        let slot = self.target_slot(program, base)?;
+        let value = match op {
+            Some(op) => {
+                let current = self.place(slot, program.ast.span(base), &indices)?.clone();
+                self.apply_binary(op, current, value, span)?
+            }
+            None => value,
+        };
+
+        *self.place(slot, program.ast.span(base), &indices)? = value;
+        Ok(())
+    }
+
+    fn place(
+        &mut self,
+        slot: Slot,
+        name_span: Span,
+        indices: &[(Value, Span)],
+    ) -> Result<&mut Value, CompilerError> {
+        let input = self.input;
+        let filename = &self.filename;
+        let error = |kind, span| CompilerError::new(kind, span, input, filename.clone());
+
+        let mut place = match self.env.get_mut(slot) {
+            Some(place) => place,
+            None => return Err(error(ErrorKind::UndefinedVariable, name_span)),
+        };
+        for (index, span) in indices {
+            place = match element_mut(place, index) {
+                Ok(place) => place,
+                Err(kind) => return Err(error(kind, *span)),
+            };
+        }
+
+        Ok(place)
+    }
+
+    fn call_function(
+        &mut self,
+        func: &Function,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, CompilerError> {
+        if unlikely(args.len() != func.arity) {
+            return Err(self.error(ErrorKind::ArgumentCountMismatch, span));
+        }
+
+        let saved = self.env.enter_call(args);
+        let result = self.eval(&func.program, func.body);
+        self.env.exit_call(saved);
+
+        finish(result)
+    }
+
+    fn eval_sequence(&mut self, program: &Rc<Program>, exprs: &[NodeId]) -> EvalResult {
+        // Functions are hoisted, matching the resolver.
+        for expr in exprs {
+            if let Expr::Func { .. } = program.ast[*expr] {
+                self.eval(program, *expr)?;
+            }
+        }
+
+        let mut last = Value::None;
+        for expr in exprs {
+            last = match program.ast[*expr] {
+                Expr::Func { .. } => Value::None,
+                _ => self.eval(program, *expr)?,
+            };
+        }
+
+        Ok(last)
+    }
+
+    fn eval_literal(&self, lit: &Literal) -> Value {
+        match lit {
+            Literal::Int(v) => Value::Int(*v),
+            Literal::Float(v) => Value::Float(*v),
+            Literal::String(v) => Value::String(v.clone()),
+            Literal::Char(v) => Value::Char(*v),
+            Literal::Bool(v) => Value::Bool(*v),
+            Literal::None => Value::None,
+        }
+    }
+
+    fn eval_unary(
+        &mut self,
+        program: &Rc<Program>,
+        op: &UnaryOp,
+        expr: NodeId,
+        span: Span,
+    ) -> EvalResult {
+        let value = self.eval(program, expr)?;
+
+        match (op, value) {
+            (UnaryOp::Neg, Value::Int(v)) => match v.checked_neg() {
+                Some(v) => Ok(Value::Int(v)),
+                None => Err(self.error(ErrorKind::IntegerOverflow, span).into()),
+            },
+            (UnaryOp::Neg, Value::Float(v)) => Ok(Value::Float(-v)),
+            (UnaryOp::Not, Value::Bool(v)) => Ok(Value::Bool(!v)),
+            (UnaryOp::BitNot, Value::Int(v)) => Ok(Value::Int(!v)),
+            _ => Err(self.error(ErrorKind::InvalidOperand, span).into()),
+        }
+    }
+
+    fn eval_binary(
+        &mut self,
+        program: &Rc<Program>,
+        op: &BinOp,
+        left: NodeId,
+        right: NodeId,
+        span: Span,
+    ) -> EvalResult {
+        // Logical operators short-circuit, so the right side is evaluated lazily.
+        if matches!(op, BinOp::And | BinOp::Or) {
+            let lhs = match self.eval(program, left)? {
+                Value::Bool(v) => v,
+                _ => {
+                    return Err(self
+                        .error(ErrorKind::InvalidOperand, program.ast.span(left))
+                        .into());
+                }
+            };
+            if (*op == BinOp::And && !lhs) || (*op == BinOp::Or && lhs) {
+                return Ok(Value::Bool(lhs));
+            }
+
+            return match self.eval(program, right)? {
+                Value::Bool(v) => Ok(Value::Bool(v)),
+                _ => Err(self
+                    .error(ErrorKind::InvalidOperand, program.ast.span(right))
+                    .into()),
+            };
+        }
+
+        let lhs = self.eval(program, left)?;
+        let rhs = self.eval(program, right)?;
+        Ok(self.apply_binary(op, lhs, rhs, span)?)
+    }
+
+    fn apply_binary(
+        &self,
+        op: &BinOp,
+        lhs: Value,
+        rhs: Value,
+        span: Span,
+    ) -> Result<Value, CompilerError> {
+        match op {
+            BinOp::Eq => self.eval_equality(lhs, rhs, span).map(Value::Bool),
+            BinOp::Neq => self.eval_equality(lhs, rhs, span).map(|v| Value::Bool(!v)),
+            BinOp::Lt | BinOp::Gt | BinOp::Lte | BinOp::Gte => {
+                self.eval_comparison(op, lhs, rhs, span)
+            }
+            _ => match (lhs, rhs) {
+                (Value::Int(a), Value::Int(b)) => self.eval_int(op, a, b, span),
+                (Value::Float(a), Value::Float(b)) => self.eval_float(op, a, b, span),
+                (Value::String(a), Value::String(b)) if *op == BinOp::Add => {
+                    Ok(Value::String(a + &b))
+                }
+                (Value::Bool(a), Value::Bool(b)) => match op {
+                    BinOp::BitAnd => Ok(Value::Bool(a & b)),
+                    BinOp::BitOr => Ok(Value::Bool(a | b)),
+                    BinOp::BitXor => Ok(Value::Bool(a ^ b)),
+                    _ => Err(self.error(ErrorKind::InvalidOperand, span)),
+                },
+                _ => Err(self.error(ErrorKind::InvalidOperand, span)),
+            },
+        }
+    }
+
+    fn eval_int(&self, op: &BinOp, a: i64, b: i64, span: Span) -> Result<Value, CompilerError> {
+        if matches!(op, BinOp::Div | BinOp::Mod) && b == 0 {
+            return Err(self.error(ErrorKind::DivisionByZero, span));
+        }
+
+        let result = match op {
+            BinOp::Add => a.checked_add(b),
+            BinOp::Sub => a.checked_sub(b),
+            BinOp::Mul => a.checked_mul(b),
+            BinOp::Div => a.checked_div(b),
+            BinOp::Mod => a.checked_rem(b),
+            BinOp::Pow => match u32::try_from(b) {
+                Ok(exp) => a.checked_pow(exp),
+                Err(_) => return Err(self.error(ErrorKind::InvalidOperand, span)),
+            },
+            BinOp::BitAnd => Some(a & b),
+            BinOp::BitOr => Some(a | b),
+            BinOp::BitXor => Some(a ^ b),
+            BinOp::Shl => u32::try_from(b).ok().and_then(|n| a.checked_shl(n)),
+            BinOp::Shr => u32::try_from(b).ok().and_then(|n| a.checked_shr(n)),
+            _ => return Err(self.error(ErrorKind::InvalidOperand, span)),
+        };
+
+        result
+            .map(Value::Int)
+            .ok_or_else(|| self.error(ErrorKind::IntegerOverflow, span))
+    }
+
+    fn eval_float(&self, op: &BinOp, a: f64, b: f64, span: Span) -> Result<Value, CompilerError> {
+        let result = match op {
+            BinOp::Add => a + b,
+            BinOp::Sub => a - b,
+            BinOp::Mul => a * b,
+            BinOp::Div => a / b,
+            BinOp::Mod => a % b,
+            BinOp::Pow => a.powf(b),
+            _ => return Err(self.error(ErrorKind::InvalidOperand, span)),
+        };
+
+        Ok(Value::Float(result))
+    }
+
+    fn eval_equality(&self, lhs: Value, rhs: Value, span: Span) -> Result<bool, CompilerError> {
+        match (&lhs, &rhs) {
+            (Value::Int(_), Value::Int(_))
+            | (Value::Float(_), Value::Float(_))
+            | (Value::String(_), Value::String(_))
+            | (Value::Char(_), Value::Char(_))
+            | (Value::Bool(_), Value::Bool(_))
+            | (Value::Array(_), Value::Array(_))
+            | (Value::None, Value::None) => Ok(lhs == rhs),
+            _ => Err(self.error(ErrorKind::InvalidOperand, span)),
+        }
+    }
+
+    fn eval_comparison(
+        &self,
+        op: &BinOp,
+        lhs: Value,
+        rhs: Value,
+        span: Span,
+    ) -> Result<Value, CompilerError> {
+        let ordering = match (&lhs, &rhs) {
+            (Value::Int(a), Value::Int(b)) => a.partial_cmp(b),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (Value::String(a), Value::String(b)) => a.partial_cmp(b),
+            (Value::Char(a), Value::Char(b)) => a.partial_cmp(b),
+            _ => return Err(self.error(ErrorKind::InvalidOperand, span)),
+        };
+
+        let result = match ordering {
+            Some(ordering) => match op {
+                BinOp::Lt => ordering.is_lt(),
+                BinOp::Gt => ordering.is_gt(),
+                BinOp::Lte => ordering.is_le(),
+                BinOp::Gte => ordering.is_ge(),
+                _ => unreachable!(),
+            },
+            // NaN compares false against everything.
+            None => false,
+        };
+
+        Ok(Value::Bool(result))
+    }
+
+    fn target_slot(&self, program: &Program, target: NodeId) -> Result<Slot, CompilerError> {
+        match (&program.ast[target], program.slots.get(target)) {
+            (Expr::Ident { .. }, Some(slot)) => Ok(*slot),
+            (Expr::Ident { span, .. }, None) => {
+                Err(self.error(ErrorKind::UndefinedVariable, *span))
+            }
+            _ => Err(self.error(ErrorKind::InvalidOperand, program.ast.span(target))),
+        }
+    }
+
+    #[cold]
+    fn error(&self, kind: ErrorKind, span: Span) -> CompilerError {
+        CompilerError::new(kind, span, self.input, self.filename.clone())
+    }
+}
+
+fn function(program: &Rc<Program>, decl: NodeId) -> Option<Function> {
+    match &program.ast[decl] {
+        Expr::Func {
+            name,
+            params,
+            body,
+            span,
+            ..
+        } => Some(Function {
+            name: *name,
+            arity: params.len(),
+            program: program.clone(),
+            body: *body,
+            span: *span,
+        }),
+        _ => None,
+    }
+}
+
+#[inline(always)]
+fn finish(result: EvalResult) -> Result<Value, CompilerError> {
+    match result {
+        Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+        Err(Unwind::Error(e)) => Err(e),
+        Err(Unwind::Break(_) | Unwind::Continue) => {
+            unreachable!("`break` and `continue` outside a loop are rejected by the parser")
+        }
+    }
+}
+
+fn element<'v>(target: &'v Value, index: &Value) -> Result<&'v Value, ErrorKind> {
+    match (target, index) {
+        (Value::Array(items), Value::Int(i)) => usize::try_from(*i)
+            .ok()
+            .and_then(|i| items.get(i))
+            .ok_or(ErrorKind::IndexOutOfBounds),
+        _ => Err(ErrorKind::InvalidOperand),
+    }
+}
+
+fn element_mut<'v>(target: &'v mut Value, index: &Value) -> Result<&'v mut Value, ErrorKind> {
+    match (target, index) {
+        (Value::Array(items), Value::Int(i)) => usize::try_from(*i)
+            .ok()
+            .and_then(|i| Rc::make_mut(items).get_mut(i))
+            .ok_or(ErrorKind::IndexOutOfBounds),
+        _ => Err(ErrorKind::InvalidOperand),
+    }
+}
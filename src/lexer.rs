@@ -1,7 +1,9 @@
 use crate::Span;
+use crate::error::push_error;
 use crate::{CompilerError, ErrorKind};
 use crate::{Token, TokenType};
 use branches::{likely, unlikely};
+use std::rc::Rc;
 use unicode_ident::{is_xid_continue, is_xid_start};
 
 const CHAR_DEC_DIGIT: u16 = 1; // 0-9
@@ -19,6 +21,7 @@ const CHAR_DOUBLE_QUOTE: u16 = 1 << 10; // "
 const CHAR_ESCAPE: u16 = 1 << 11; // \
 const CHAR_NEWLINE: u16 = 1 << 12; // \n
 const CHAR_NEWLINE_AND_ESCAPE: u16 = CHAR_NEWLINE | CHAR_ESCAPE; // \, \n
+const CHAR_UNDERSCORE: u16 = 1 << 13; // _, digit separator in numbers
 
 #[rustfmt::skip]
 const CHAR_TABLE: [u16; 256] = [
@@ -32,13 +35,13 @@ const CHAR_TABLE: [u16; 256] = [
     0x0080, 0x0040, 0x0500, 0x0000, 0x0000, 0x0040, 0x0040, 0x0300,
     0x0040, 0x0040, 0x0040, 0x0040, 0x0040, 0x0040, 0x0040, 0x0040,
     0x000F, 0x000F, 0x000D, 0x000D, 0x000D, 0x000D, 0x000D, 0x000D,
-    0x0009, 0x0009, 0x0040, 0x0040, 0x0040, 0x0040, 0x0040, 0x0000,
+    0x0009, 0x0009, 0x0040, 0x0040, 0x0040, 0x0040, 0x0040, 0x0040,
 
     // 0x40
     0x0000, 0x0018, 0x0018, 0x0018, 0x0018, 0x0018, 0x0018, 0x0010,
     0x0010, 0x0010, 0x0010, 0x0010, 0x0010, 0x0010, 0x0010, 0x0010,
     0x0010, 0x0010, 0x0010, 0x0010, 0x0010, 0x0010, 0x0010, 0x0010,
-    0x0010, 0x0010, 0x0010, 0x0040, 0x0800, 0x0040, 0x0040, 0x0010,
+    0x0010, 0x0010, 0x0010, 0x0040, 0x0800, 0x0040, 0x0040, 0x2010,
 
     // 0x60
     0x0000, 0x0018, 0x0018, 0x0018, 0x0018, 0x0018, 0x0018, 0x0010,
@@ -71,299 +74,255 @@ const CHAR_TABLE: [u16; 256] = [
     0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
 ];
 
+// Multi-byte symbols are matched by their first byte, then by the longest
+// `follow` sequence that matches the bytes after it (so `<<=` wins over `<<`).
 struct SymbolRule {
     max_len: u8,
-    expect1: u8,
-    expect2: u8,
     single: TokenType,
-    double1: TokenType,
-    double2: TokenType,
+    follow: &'static [(&'static [u8], TokenType)],
 }
 
 const SYMBOL_LOOKUP: [SymbolRule; 128] = {
     const DEFAULT: SymbolRule = SymbolRule {
         max_len: 0,
-        expect1: 0,
-        expect2: 0,
         single: TokenType::Unknown,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[],
     };
 
     let mut array = [DEFAULT; 128];
     array[b'!' as usize] = SymbolRule {
         max_len: 2,
-        expect1: b'=',
-        expect2: 0,
         single: TokenType::Not,
-        double1: TokenType::Ne,
-        double2: TokenType::Unknown,
+        follow: &[(b"=", TokenType::Ne)],
     };
 
     array[b'%' as usize] = SymbolRule {
-        max_len: 1,
-        expect1: 0,
-        expect2: 0,
+        max_len: 2,
         single: TokenType::Mod,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[(b"=", TokenType::ModAssign)],
     };
 
     array[b'&' as usize] = SymbolRule {
         max_len: 2,
-        expect1: b'&',
-        expect2: 0,
         single: TokenType::Ampersand,
-        double1: TokenType::And,
-        double2: TokenType::Unknown,
+        follow: &[(b"&", TokenType::And), (b"=", TokenType::AmpersandAssign)],
     };
 
     array[b'(' as usize] = SymbolRule {
         max_len: 1,
-        expect1: 0,
-        expect2: 0,
         single: TokenType::LParen,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[],
     };
 
     array[b')' as usize] = SymbolRule {
         max_len: 1,
-        expect1: 0,
-        expect2: 0,
         single: TokenType::RParen,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[],
     };
 
     array[b'*' as usize] = SymbolRule {
-        max_len: 2,
-        expect1: b'*',
-        expect2: 0,
+        max_len: 3,
         single: TokenType::Star,
-        double1: TokenType::Power,
-        double2: TokenType::Unknown,
+        follow: &[
+            (b"*=", TokenType::PowerAssign),
+            (b"*", TokenType::Power),
+            (b"=", TokenType::StarAssign),
+        ],
     };
 
     array[b'+' as usize] = SymbolRule {
-        max_len: 1,
-        expect1: 0,
-        expect2: 0,
+        max_len: 2,
         single: TokenType::Plus,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[(b"=", TokenType::PlusAssign)],
     };
 
     array[b',' as usize] = SymbolRule {
         max_len: 1,
-        expect1: 0,
-        expect2: 0,
         single: TokenType::Comma,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[],
     };
 
     array[b'-' as usize] = SymbolRule {
-        max_len: 1,
-        expect1: 0,
-        expect2: 0,
+        max_len: 2,
         single: TokenType::Minus,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[(b">", TokenType::Arrow), (b"=", TokenType::MinusAssign)],
     };
 
     array[b'.' as usize] = SymbolRule {
-        max_len: 1,
-        expect1: 0,
-        expect2: 0,
+        max_len: 3,
         single: TokenType::Dot,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[(b".=", TokenType::DotDotEq), (b".", TokenType::DotDot)],
     };
 
     array[b'/' as usize] = SymbolRule {
-        max_len: 1,
-        expect1: 0,
-        expect2: 0,
+        max_len: 2,
         single: TokenType::Slash,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[(b"=", TokenType::SlashAssign)],
     };
 
     array[b':' as usize] = SymbolRule {
-        max_len: 1,
-        expect1: 0,
-        expect2: 0,
+        max_len: 2,
         single: TokenType::Colon,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[(b":", TokenType::DoubleColon)],
     };
 
     array[b';' as usize] = SymbolRule {
         max_len: 1,
-        expect1: 0,
-        expect2: 0,
         single: TokenType::Semicolon,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[],
     };
 
     array[b'<' as usize] = SymbolRule {
-        max_len: 2,
-        expect1: b'=',
-        expect2: b'<',
+        max_len: 3,
         single: TokenType::Lt,
-        double1: TokenType::LtE,
-        double2: TokenType::BitwiseShl,
+        follow: &[
+            (b"<=", TokenType::BitwiseShlAssign),
+            (b"=", TokenType::LtE),
+            (b"<", TokenType::BitwiseShl),
+        ],
     };
 
     array[b'=' as usize] = SymbolRule {
         max_len: 2,
-        expect1: b'=',
-        expect2: 0,
         single: TokenType::Assign,
-        double1: TokenType::Eq,
-        double2: TokenType::Unknown,
+        follow: &[(b"=", TokenType::Eq), (b">", TokenType::FatArrow)],
     };
 
     array[b'>' as usize] = SymbolRule {
-        max_len: 2,
-        expect1: b'=',
-        expect2: b'>',
+        max_len: 3,
         single: TokenType::Gt,
-        double1: TokenType::GtE,
-        double2: TokenType::BitwiseShr,
+        follow: &[
+            (b">=", TokenType::BitwiseShrAssign),
+            (b"=", TokenType::GtE),
+            (b">", TokenType::BitwiseShr),
+        ],
+    };
+
+    array[b'?' as usize] = SymbolRule {
+        max_len: 1,
+        single: TokenType::Question,
+        follow: &[],
     };
 
     array[b'[' as usize] = SymbolRule {
         max_len: 1,
-        expect1: 0,
-        expect2: 0,
         single: TokenType::LBracket,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[],
     };
 
     array[b']' as usize] = SymbolRule {
         max_len: 1,
-        expect1: 0,
-        expect2: 0,
         single: TokenType::RBracket,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[],
     };
 
     array[b'^' as usize] = SymbolRule {
-        max_len: 1,
-        expect1: 0,
-        expect2: 0,
+        max_len: 2,
         single: TokenType::BitwiseXor,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[(b"=", TokenType::BitwiseXorAssign)],
     };
 
     array[b'{' as usize] = SymbolRule {
         max_len: 1,
-        expect1: 0,
-        expect2: 0,
         single: TokenType::LBrace,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[],
     };
 
     array[b'|' as usize] = SymbolRule {
         max_len: 2,
-        expect1: b'|',
-        expect2: 0,
         single: TokenType::Pipe,
-        double1: TokenType::Or,
-        double2: TokenType::Unknown,
+        follow: &[(b"|", TokenType::Or), (b"=", TokenType::PipeAssign)],
     };
 
     array[b'}' as usize] = SymbolRule {
         max_len: 1,
-        expect1: 0,
-        expect2: 0,
         single: TokenType::RBrace,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[],
     };
 
     array[b'~' as usize] = SymbolRule {
         max_len: 1,
-        expect1: 0,
-        expect2: 0,
         single: TokenType::Tilde,
-        double1: TokenType::Unknown,
-        double2: TokenType::Unknown,
+        follow: &[],
     };
 
     array
 };
 
-const KEYWORD_TABLE_SIZE: usize = 61;
+const KEYWORD_TABLE_SIZE: usize = 72;
 const KEYWORD_TABLE: [(Option<TokenType>, &[u8]); KEYWORD_TABLE_SIZE] = [
-    (Some(TokenType::While), b"while"),
-    (Some(TokenType::Loop), b"loop"),
     (None, b""),
+    (Some(TokenType::Let), b"let"),
     (None, b""),
-    (Some(TokenType::Return), b"return"),
+    (Some(TokenType::For), b"for"),
     (None, b""),
-    (Some(TokenType::TypeInt), b"int"),
-    (Some(TokenType::Mut), b"mut"),
-    (Some(TokenType::TypeString), b"str"),
+    (Some(TokenType::Enum), b"enum"),
     (None, b""),
     (None, b""),
-    (Some(TokenType::TypeBoolean), b"bool"),
     (None, b""),
     (None, b""),
     (None, b""),
     (None, b""),
     (None, b""),
     (None, b""),
-    (Some(TokenType::Else), b"else"),
+    (None, b""),
+    (None, b""),
+    (None, b""),
+    (None, b""),
+    (Some(TokenType::Mut), b"mut"),
+    (Some(TokenType::False), b"false"),
+    (None, b""),
     (Some(TokenType::Match), b"match"),
-    (Some(TokenType::Const), b"const"),
-    (Some(TokenType::For), b"for"),
-    (Some(TokenType::If), b"if"),
     (None, b""),
     (None, b""),
+    (Some(TokenType::Return), b"return"),
+    (None, b""),
     (None, b""),
+    (Some(TokenType::If), b"if"),
     (None, b""),
+    (Some(TokenType::Struct), b"struct"),
     (None, b""),
-    (Some(TokenType::Break), b"break"),
+    (None, b""),
+    (Some(TokenType::TypeBoolean), b"bool"),
+    (Some(TokenType::While), b"while"),
     (None, b""),
     (Some(TokenType::In), b"in"),
     (None, b""),
-    (Some(TokenType::Enum), b"enum"),
+    (None, b""),
     (Some(TokenType::Impl), b"impl"),
     (None, b""),
-    (Some(TokenType::TypeFloat), b"float"),
-    (Some(TokenType::Func), b"func"),
+    (Some(TokenType::None), b"None"),
+    (Some(TokenType::Continue), b"continue"),
     (None, b""),
+    (Some(TokenType::TypeInt), b"int"),
     (None, b""),
     (None, b""),
-    (Some(TokenType::Continue), b"continue"),
+    (Some(TokenType::Loop), b"loop"),
+    (Some(TokenType::Const), b"const"),
     (None, b""),
+    (Some(TokenType::Break), b"break"),
     (None, b""),
     (None, b""),
+    (Some(TokenType::True), b"true"),
     (None, b""),
+    (Some(TokenType::TypeChar), b"char"),
+    (Some(TokenType::Import), b"import"),
     (None, b""),
-    (Some(TokenType::Struct), b"struct"),
-    (Some(TokenType::False), b"false"),
+    (Some(TokenType::TypeString), b"str"),
     (None, b""),
     (None, b""),
-    (Some(TokenType::True), b"true"),
     (None, b""),
     (None, b""),
     (None, b""),
     (None, b""),
     (None, b""),
-    (Some(TokenType::None), b"None"),
+    (Some(TokenType::Else), b"else"),
+    (Some(TokenType::TypeFloat), b"float"),
+    (None, b""),
+    (Some(TokenType::Func), b"func"),
+    (None, b""),
     (None, b""),
     (None, b""),
-    (Some(TokenType::Let), b"let"),
-    (Some(TokenType::Import), b"import"),
 ];
 
 const KEYWORD_MAX_LEN: usize = 8;
@@ -377,26 +336,193 @@ fn hash(bytes: &[u8]) -> usize {
     h % KEYWORD_TABLE_SIZE
 }
 
+// Progress through one `f"..."` literal; they nest through their expressions.
+#[derive(Debug)]
+struct Format {
+    start: usize,
+    // Braces opened inside the current `{...}` expression.
+    braces: usize,
+    in_text: bool,
+}
+
 #[derive(Debug)]
 pub struct Lexer<'a> {
+    // Errors share `source`; scanning goes through the plain slice.
+    source: &'a Rc<[u8]>,
     input: &'a [u8],
     input_len: usize,
     filename: String,
     pos: usize,
+    formats: Vec<Format>,
+    lossless: bool,
+    // Where the leading trivia of the next token starts; input skipped after
+    // an error becomes part of it.
+    trivia_start: usize,
+    // Set once `Eof` has been yielded by the iterator.
+    finished: bool,
 }
 
 impl<'a> Lexer<'a> {
     #[inline(always)]
-    pub fn new(filename: String, input: &'a [u8]) -> Self {
+    pub fn new(filename: String, source: &'a Rc<[u8]>) -> Self {
         Self {
             filename,
-            input_len: input.len(),
-            input,
+            input_len: source.len(),
+            source,
+            input: source,
             pos: 0,
+            formats: Vec::new(),
+            lossless: false,
+            trivia_start: 0,
+            finished: false,
+        }
+    }
+
+    // Keeps whitespace and comments as trivia on the tokens, so that the
+    // leading trivia, text and trailing trivia of all tokens add up to the input.
+    #[inline(always)]
+    pub fn lossless(filename: String, source: &'a Rc<[u8]>) -> Self {
+        Self {
+            lossless: true,
+            ..Self::new(filename, source)
         }
     }
 
+    // Lexes the remaining input, ending with `Eof`, and gathers every error on the way.
+    pub fn tokenize_all(&mut self) -> Result<Vec<Token>, Vec<CompilerError>> {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for result in self.by_ref() {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(e) => push_error(&mut errors, e),
+            }
+        }
+
+        if likely(errors.is_empty()) {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // After an error the lexer has already skipped the offending input, so
+    // calling it again carries on with the rest of the file.
     pub fn next_token(&mut self) -> Result<Token, CompilerError> {
+        let start_pos = self.pos;
+        let result = self.lex_token();
+        let mut token = match result {
+            Ok(token) => token,
+            Err(e) => {
+                self.recover(&e, start_pos);
+                return Err(e);
+            }
+        };
+
+        if unlikely(self.lossless) {
+            // Trivia up to the end of the token's line trails it, the rest leads the next one.
+            token.leading = Span::new(self.trivia_start, token.span.start);
+            if !self.formats.last().is_some_and(|format| format.in_text) {
+                token.trailing = self.read_trailing_trivia();
+            }
+            self.trivia_start = self.pos;
+        }
+
+        Ok(token)
+    }
+
+    // Moves past the input that caused `error` so that lexing can resume.
+    #[cold]
+    fn recover(&mut self, error: &CompilerError, start_pos: usize) {
+        let resume = match error.kind {
+            // Everything up to the end of the input belongs to the comment.
+            ErrorKind::UnclosedComment => self.input_len,
+            // The string ran to the end of its line; its `f"` nesting is gone too.
+            ErrorKind::UnclosedString => {
+                self.formats.clear();
+                error.span.end
+            }
+            _ => error.span.end,
+        };
+        self.pos = self.pos.max(resume);
+
+        if unlikely(self.pos <= start_pos && start_pos < self.input_len) {
+            self.pos = start_pos + utf8_len(self.input[start_pos]);
+        }
+    }
+
+    fn lex_token(&mut self) -> Result<Token, CompilerError> {
+        // Inside `f"..."` the lexer alternates between text and `{...}` expressions.
+        match self.formats.last() {
+            Some(format) if format.in_text => return self.read_format_text(),
+            Some(_) => {}
+            None => return self.scan_token(),
+        }
+
+        let token = self.scan_token()?;
+        if let Some(format) = self.formats.last_mut() {
+            match token.token_type {
+                TokenType::LBrace => format.braces += 1,
+                TokenType::RBrace if format.braces == 0 => format.in_text = true,
+                TokenType::RBrace => format.braces -= 1,
+                // Cut off inside `{...}`, which leaves the string itself unclosed.
+                TokenType::Eof => {
+                    return Err(CompilerError::new(
+                        ErrorKind::UnclosedString,
+                        Span::new(format.start, token.span.start),
+                        self.source,
+                        self.filename.clone(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(token)
+    }
+
+    fn read_trailing_trivia(&mut self) -> Span {
+        let start_pos = self.pos;
+        let mut pos = start_pos;
+        let input_len = self.input_len;
+
+        while likely(pos < input_len) {
+            let b = self.peek_unlocked(&pos);
+            if b != b'\n' && (CHAR_TABLE[b as usize] & CHAR_WHITESPACE) != 0 {
+                self.advance(&mut pos, 1);
+            } else if b == b'/' && self.peek(&(pos + 1)) == Some(b'/') {
+                if self.doc_comment_kind(pos).is_some() {
+                    break;
+                }
+                self.skip_line_comment(&mut pos);
+            } else if b == b'/' && self.peek(&(pos + 1)) == Some(b'*') {
+                // An unclosed comment is left for the next token to report.
+                let mut end = pos;
+                if self.skip_block_comment(&mut end).is_err() {
+                    break;
+                }
+                pos = end;
+            } else {
+                break;
+            }
+        }
+
+        self.pos = pos;
+        Span::new(start_pos, pos)
+    }
+
+    // `///` documents the next declaration and `//!` the whole file, but
+    // `////` is a plain comment again.
+    fn doc_comment_kind(&self, pos: usize) -> Option<TokenType> {
+        match (self.peek(&(pos + 2)), self.peek(&(pos + 3))) {
+            (Some(b'/'), Some(b'/')) => None,
+            (Some(b'/'), _) => Some(TokenType::DocComment),
+            (Some(b'!'), _) => Some(TokenType::InnerDocComment),
+            _ => None,
+        }
+    }
+
+    fn scan_token(&mut self) -> Result<Token, CompilerError> {
         loop {
             let start_pos = self.pos;
             let mut pos = start_pos;
@@ -410,20 +536,22 @@ impl<'a> Lexer<'a> {
 
             // skip comment
             if b == b'/' {
-                self.advance(&mut pos, 1);
-                if self.peek(&pos) == Some(b'/') {
-                    self.advance(&mut pos, 1);
-                    self.skip_line_comment();
-                } else if unlikely(self.peek(&pos) == Some(b'*')) {
-                    self.advance(&mut pos, 1);
-                    self.skip_block_comment();
-                } else {
+                let next = self.peek(&(pos + 1));
+                if next == Some(b'/') {
+                    let doc = self.doc_comment_kind(pos);
+                    self.skip_line_comment(&mut pos);
+                    if let Some(kind) = doc {
+                        let span = Span::new(self.pos, pos);
+                        self.pos = pos;
+                        return Ok(Token::new(kind, span));
+                    }
+                    self.pos = pos;
+                    continue;
+                } else if unlikely(next == Some(b'*')) {
+                    self.skip_block_comment(&mut pos)?;
                     self.pos = pos;
-                    return Ok(Token::new(TokenType::Slash, Span::new(start_pos, pos)));
+                    continue;
                 }
-
-                self.pos = pos;
-                continue;
             }
 
             break {
@@ -434,7 +562,14 @@ impl<'a> Lexer<'a> {
                 } else if (mask & CHAR_DEC_DIGIT) != 0 {
                     self.read_number()
                 } else if (mask & CHAR_ASCII_ID_START) != 0 {
-                    self.read_identifier()
+                    if unlikely(b == b'f') && self.peek(&(pos + 1)) == Some(b'"') {
+                        self.read_format_start()
+                    } else {
+                        match self.raw_string_hashes(pos) {
+                            Some(hashes) => self.read_raw_string(hashes),
+                            None => self.read_identifier(),
+                        }
+                    }
                 } else if (mask & CHAR_QUOTE) != 0 {
                     self.read_string()
                 } else if unlikely(is_xid_start(self.peek_char_unlocked())) {
@@ -444,8 +579,8 @@ impl<'a> Lexer<'a> {
                         self.advance_char(&mut pos);
                         return Err(CompilerError::new(
                             ErrorKind::UnexpectedCharacter,
-                            Span::new(start_pos, pos),
-                            self.input,
+                            Span::new(self.pos, pos),
+                            self.source,
                             self.filename.clone(),
                         ));
                     }
@@ -458,14 +593,15 @@ impl<'a> Lexer<'a> {
 
     fn lookup_keyword(&self, span: Span) -> TokenType {
         let bytes = &self.input[span.start..span.end];
-        let h = hash(bytes);
-
+        // Checked before hashing, which would overflow on long identifiers.
         if bytes.len() > KEYWORD_MAX_LEN
             || unlikely((CHAR_TABLE[bytes[0] as usize] & CHAR_UTF8_START) != 0)
         {
             return TokenType::Identifier;
         }
 
+        let h = hash(bytes);
+
         if let (Some(token_type), expected_bytes) = unsafe { KEYWORD_TABLE.get_unchecked(h) } {
             if likely(bytes == *expected_bytes) {
                 *token_type
@@ -515,6 +651,10 @@ impl<'a> Lexer<'a> {
     }
 
     fn read_string(&mut self) -> Result<Token, CompilerError> {
+        if unlikely(self.input[self.pos..].starts_with(b"\"\"\"")) {
+            return self.read_multiline_string();
+        }
+
         let start_pos = self.pos;
         let mut pos = start_pos;
         let quote_mask: u16 = if self.peek_unlocked(&pos) == b'"' {
@@ -525,6 +665,8 @@ impl<'a> Lexer<'a> {
         self.advance(&mut pos, 1);
 
         let input_len = self.input.len();
+        // Reported once the end of the string is found, so that lexing resumes after it.
+        let mut invalid_escape = None;
 
         while likely(pos < input_len) {
             let b = self.peek_unlocked(&pos);
@@ -540,32 +682,246 @@ impl<'a> Lexer<'a> {
             }
 
             if unlikely((mask & CHAR_ESCAPE) != 0) {
-                self.advance(&mut pos, 2);
+                // A trailing `\` is reported as an unclosed string below.
+                if unlikely(pos + 1 >= input_len) {
+                    self.advance(&mut pos, 1);
+                    continue;
+                }
+
+                let len = match decode_escape(&self.input[pos..]) {
+                    Ok((_, len)) => len,
+                    Err(len) => {
+                        invalid_escape.get_or_insert(Span::new(pos, pos + len));
+                        len
+                    }
+                };
+                self.advance(&mut pos, len);
                 continue;
             }
 
             if unlikely((mask & CHAR_NEWLINE) != 0) {
-                return Err(CompilerError::new(
-                    ErrorKind::UnclosedString,
-                    Span::new(start_pos, pos),
-                    self.input,
-                    self.filename.clone(),
-                ));
+                break;
             }
         }
 
-        if unlikely(self.peek(&pos).is_none()) {
+        let closed = self
+            .peek(&pos)
+            .is_some_and(|b| (CHAR_TABLE[b as usize] & quote_mask) != 0);
+        if likely(closed) {
+            self.advance(&mut pos, 1);
+        }
+
+        if let Some(span) = invalid_escape {
+            self.pos = pos;
+            return Err(CompilerError::new(
+                ErrorKind::InvalidEscape,
+                span,
+                self.source,
+                self.filename.clone(),
+            ));
+        }
+
+        if unlikely(!closed) {
             return Err(CompilerError::new(
                 ErrorKind::UnclosedString,
                 Span::new(start_pos, pos),
-                self.input,
+                self.source,
                 self.filename.clone(),
             ));
         }
 
-        self.advance(&mut pos, 1);
+        let span = Span::new(start_pos, pos);
+        if quote_mask == CHAR_SINGLE_QUOTE {
+            self.check_char(span)?;
+            self.pos = pos;
+            return Ok(Token::new(TokenType::Char, span));
+        }
+
+        self.pos = pos;
+        Ok(Token::new(TokenType::String, span))
+    }
+
+    // `r"..."` and `r#"..."#` contain no escapes and may span lines.
+    fn raw_string_hashes(&self, pos: usize) -> Option<usize> {
+        if likely(self.peek_unlocked(&pos) != b'r') {
+            return None;
+        }
+
+        let hashes = self.input[pos + 1..]
+            .iter()
+            .take_while(|b| **b == b'#')
+            .count();
+        match self.peek(&(pos + 1 + hashes)) {
+            Some(b'"') => Some(hashes),
+            _ => None,
+        }
+    }
+
+    fn read_raw_string(&mut self, hashes: usize) -> Result<Token, CompilerError> {
+        let start_pos = self.pos;
+        let mut pos = start_pos;
+        self.advance(&mut pos, hashes + 2);
+
+        let input_len = self.input_len;
+        while likely(pos < input_len) {
+            if unlikely(self.peek_unlocked(&pos) == b'"')
+                && self.input[pos + 1..]
+                    .iter()
+                    .take(hashes)
+                    .take_while(|b| **b == b'#')
+                    .count()
+                    == hashes
+            {
+                self.advance(&mut pos, hashes + 1);
+                self.pos = pos;
+                return Ok(Token::new(TokenType::String, Span::new(start_pos, pos)));
+            }
+            self.advance(&mut pos, 1);
+        }
+
+        self.pos = pos;
+        Err(CompilerError::new(
+            ErrorKind::UnclosedString,
+            Span::new(start_pos, start_pos + hashes + 2),
+            self.source,
+            self.filename.clone(),
+        ))
+    }
+
+    // `"""..."""` may span lines; the parser strips its common indentation.
+    fn read_multiline_string(&mut self) -> Result<Token, CompilerError> {
+        let start_pos = self.pos;
+        let mut pos = start_pos;
+        self.advance(&mut pos, 3);
+
+        let input_len = self.input_len;
+        let mut invalid_escape = None;
+        while likely(pos < input_len) {
+            match self.peek_unlocked(&pos) {
+                b'"' if self.input[pos..].starts_with(b"\"\"\"") => {
+                    self.advance(&mut pos, 3);
+                    self.pos = pos;
+                    return match invalid_escape {
+                        None => Ok(Token::new(TokenType::String, Span::new(start_pos, pos))),
+                        Some(span) => Err(CompilerError::new(
+                            ErrorKind::InvalidEscape,
+                            span,
+                            self.source,
+                            self.filename.clone(),
+                        )),
+                    };
+                }
+                b'\\' if pos + 1 < input_len => {
+                    let len = match decode_escape(&self.input[pos..]) {
+                        Ok((_, len)) => len,
+                        Err(len) => {
+                            invalid_escape.get_or_insert(Span::new(pos, pos + len));
+                            len
+                        }
+                    };
+                    self.advance(&mut pos, len);
+                }
+                _ => self.advance(&mut pos, 1),
+            }
+        }
+
+        self.pos = pos;
+        Err(CompilerError::new(
+            ErrorKind::UnclosedString,
+            Span::new(start_pos, start_pos + 3),
+            self.source,
+            self.filename.clone(),
+        ))
+    }
+
+    fn read_format_start(&mut self) -> Result<Token, CompilerError> {
+        let start_pos = self.pos;
+        self.pos += 2;
+        self.formats.push(Format {
+            start: start_pos,
+            braces: 0,
+            in_text: true,
+        });
+
+        Ok(Token::new(
+            TokenType::FormatStart,
+            Span::new(start_pos, self.pos),
+        ))
+    }
+
+    // Emits the text up to the next `{` expression or the closing quote.
+    // `{{` and `}}` stand for literal braces.
+    fn read_format_text(&mut self) -> Result<Token, CompilerError> {
+        let start_pos = self.pos;
+        let mut pos = start_pos;
+        let input_len = self.input_len;
+
+        let error = |kind, span| CompilerError::new(kind, span, self.source, self.filename.clone());
+
+        while likely(pos < input_len) {
+            match self.peek_unlocked(&pos) {
+                b'{' if self.peek(&(pos + 1)) == Some(b'{') => self.advance(&mut pos, 2),
+                b'}' if self.peek(&(pos + 1)) == Some(b'}') => self.advance(&mut pos, 2),
+                b'"' | b'{' if pos > start_pos => break,
+                b'"' => {
+                    self.formats.pop();
+                    self.pos = pos + 1;
+                    return Ok(Token::new(TokenType::FormatEnd, Span::new(pos, pos + 1)));
+                }
+                b'{' => {
+                    let format = self.formats.last_mut().unwrap();
+                    format.in_text = false;
+                    format.braces = 0;
+                    self.pos = pos + 1;
+                    return Ok(Token::new(TokenType::LBrace, Span::new(pos, pos + 1)));
+                }
+                b'}' => {
+                    return Err(error(ErrorKind::InvalidSyntax, Span::new(pos, pos + 1)));
+                }
+                b'\n' => break,
+                b'\\' if pos + 1 < input_len => match decode_escape(&self.input[pos..]) {
+                    Ok((_, len)) => self.advance(&mut pos, len),
+                    Err(len) => {
+                        return Err(error(ErrorKind::InvalidEscape, Span::new(pos, pos + len)));
+                    }
+                },
+                _ => self.advance(&mut pos, 1),
+            }
+        }
+
+        if unlikely(!matches!(self.peek(&pos), Some(b'"' | b'{'))) {
+            let start = self.formats.last().unwrap().start;
+            return Err(error(ErrorKind::UnclosedString, Span::new(start, pos)));
+        }
+
         self.pos = pos;
-        Ok(Token::new(TokenType::String, Span::new(start_pos, pos)))
+        Ok(Token::new(TokenType::FormatText, Span::new(start_pos, pos)))
+    }
+
+    // A char literal holds exactly one Unicode scalar, possibly escaped.
+    fn check_char(&self, span: Span) -> Result<(), CompilerError> {
+        let content = &self.input[span.start + 1..span.end - 1];
+        let mut count = 0;
+        let mut pos = 0;
+        while pos < content.len() {
+            pos += match content[pos] {
+                b'\\' => decode_escape(&content[pos..]).map_or(1, |(_, len)| len),
+                b => utf8_len(b),
+            };
+            count += 1;
+        }
+
+        let kind = match count {
+            1 => return Ok(()),
+            0 => ErrorKind::EmptyChar,
+            _ => ErrorKind::MultipleChars,
+        };
+        Err(CompilerError::new(
+            kind,
+            span,
+            self.source,
+            self.filename.clone(),
+        ))
     }
 
     fn read_symbol(&mut self) -> Result<Token, CompilerError> {
@@ -580,65 +936,77 @@ impl<'a> Lexer<'a> {
         if unlikely(symbol_info.max_len == 0) {
             return Err(CompilerError::new(
                 ErrorKind::UnexpectedCharacter,
-                Span::new(start_pos, start_pos),
-                self.input,
+                Span::new(start_pos, pos),
+                self.source,
                 self.filename.clone(),
             ));
         }
 
-        let can_peek_next = unlikely(pos < self.input_len - 1);
         let mut symbol_type = symbol_info.single;
-        let mut symbol_span = Span::new(start_pos, pos);
+        let rest = &self.input[pos..];
 
-        if likely(can_peek_next) {
-            if self.peek_unlocked(&pos) == symbol_info.expect1 {
-                symbol_type = symbol_info.double1;
-                self.advance(&mut pos, 1);
-                symbol_span = Span::new(start_pos, pos);
-            } else if unlikely(self.peek_unlocked(&pos) == symbol_info.expect2) {
-                symbol_type = symbol_info.double2;
-                self.advance(&mut pos, 1);
-                symbol_span = Span::new(start_pos, pos);
+        for (follow, token_type) in symbol_info.follow {
+            if rest.starts_with(follow) {
+                symbol_type = *token_type;
+                self.advance(&mut pos, follow.len());
+                break;
             }
         }
 
         self.pos = pos;
-        Ok(Token::new(symbol_type, symbol_span))
+        Ok(Token::new(symbol_type, Span::new(start_pos, pos)))
     }
 
+    // Accepts `_` separators, `0b`/`0o`/`0x` prefixes and a type suffix such as
+    // `u8` or `f32`; the parser computes and range-checks the value.
     fn read_number(&mut self) -> Result<Token, CompilerError> {
         let start_pos = self.pos;
         let mut pos = start_pos;
         let mut num_type = TokenType::Int;
+        let mut valid = true;
 
-        if self.peek_unlocked(&pos) == b'0' {
-            self.advance(&mut pos, 1);
-            let can_peek_next = unlikely(pos < self.input_len);
-            if unlikely(!can_peek_next) {
-                return Ok(Token::new(TokenType::Int, Span::new(start_pos, self.pos)));
+        let radix_mask = match (self.peek_unlocked(&pos), self.peek(&(pos + 1))) {
+            (b'0', Some(b'b' | b'B')) => Some(CHAR_BIN_DIGIT),
+            (b'0', Some(b'o' | b'O')) => Some(CHAR_OCT_DIGIT),
+            (b'0', Some(b'x' | b'X')) => Some(CHAR_HEX_DIGIT),
+            _ => None,
+        };
+
+        match radix_mask {
+            Some(mask) => {
+                self.advance(&mut pos, 2);
+                let digits_start = pos;
+                self.read_bytes(&mut pos, mask | CHAR_UNDERSCORE);
+                valid = self.input[digits_start..pos].iter().any(|b| *b != b'_');
             }
+            None => {
+                valid &= self.read_decimal_digits(&mut num_type);
+                pos = self.pos;
+            }
+        }
 
-            match self.peek_unlocked(&pos) {
-                b'b' | b'B' => {
-                    self.advance(&mut pos, 1);
-                    self.read_bytes(&mut pos, CHAR_BIN_DIGIT);
-                }
-                b'o' | b'O' => {
-                    self.advance(&mut pos, 1);
-                    self.read_bytes(&mut pos, CHAR_OCT_DIGIT);
-                }
-                b'x' | b'X' => {
-                    self.advance(&mut pos, 1);
-                    self.read_bytes(&mut pos, CHAR_HEX_DIGIT);
-                }
-                _ => {
-                    self.read_decimal_digits(&mut num_type);
-                    pos = self.pos
-                }
+        // Digits that do not fit the radix end up here too, e.g. the `2` of `0b12`.
+        let suffix_start = pos;
+        self.read_bytes(&mut pos, CHAR_ASCII_ID_CONTINUE);
+        match &self.input[suffix_start..pos] {
+            b"" => {}
+            b"i8" | b"i16" | b"i32" | b"i64" | b"u8" | b"u16" | b"u32" | b"u64" => {
+                valid &= num_type == TokenType::Int;
             }
-        } else {
-            self.read_decimal_digits(&mut num_type);
-            pos = self.pos;
+            b"f32" | b"f64" => {
+                valid &= radix_mask.is_none();
+                num_type = TokenType::Float;
+            }
+            _ => valid = false,
+        }
+
+        if unlikely(!valid) {
+            return Err(CompilerError::new(
+                ErrorKind::InvalidNumber,
+                Span::new(start_pos, pos),
+                self.source,
+                self.filename.clone(),
+            ));
         }
 
         self.pos = pos;
@@ -660,26 +1028,33 @@ impl<'a> Lexer<'a> {
         self.pos = *pos;
     }
 
-    fn read_decimal_digits(&mut self, num_type: &mut TokenType) {
+    // Returns `false` for an exponent without digits, such as `1e`.
+    fn read_decimal_digits(&mut self, num_type: &mut TokenType) -> bool {
         let mut pos = self.pos;
-        self.read_bytes(&mut pos, CHAR_DEC_DIGIT);
+        let digits = CHAR_DEC_DIGIT | CHAR_UNDERSCORE;
+        self.read_bytes(&mut pos, digits);
 
-        if unlikely(self.peek(&pos) == Some(b'.')) {
+        // `1..2` is a range, not the float `1.` followed by `.2`.
+        if unlikely(self.peek(&pos) == Some(b'.')) && self.peek(&(pos + 1)) != Some(b'.') {
             *num_type = TokenType::Float;
             self.advance(&mut pos, 1);
-            self.read_bytes(&mut pos, CHAR_DEC_DIGIT);
+            self.read_bytes(&mut pos, digits);
         }
 
+        let mut valid = true;
         if unlikely(matches!(self.peek(&pos), Some(b'e' | b'E'))) {
             *num_type = TokenType::Float;
             self.advance(&mut pos, 1);
             if matches!(self.peek(&pos), Some(b'+' | b'-')) {
                 self.advance(&mut pos, 1);
             }
-            self.read_bytes(&mut pos, CHAR_DEC_DIGIT);
+            let exponent = pos;
+            self.read_bytes(&mut pos, digits);
+            valid = self.input[exponent..pos].iter().any(|b| b.is_ascii_digit());
         }
 
         self.pos = pos;
+        valid
     }
 
     fn advance_char(&mut self, pos: &mut usize) {
@@ -757,37 +1132,130 @@ impl<'a> Lexer<'a> {
         *pos += n;
     }
 
-    fn skip_block_comment(&mut self) {
-        let mut pos = self.pos;
+    // Block comments nest, so `/* a /* b */ c */` is a single comment.
+    fn skip_block_comment(&self, pos: &mut usize) -> Result<(), CompilerError> {
+        let start_pos = *pos;
         let input_len = self.input_len;
+        let mut depth = 0usize;
 
-        while likely(pos < input_len) {
-            match self.peek_unlocked(&pos) {
-                b'*' => {
-                    self.advance(&mut pos, 1);
-                    if unlikely(self.peek(&pos) == Some(b'/')) {
-                        self.advance(&mut pos, 1);
-                        break;
-                    }
+        while likely(*pos < input_len) {
+            match (self.peek_unlocked(pos), self.peek(&(*pos + 1))) {
+                (b'/', Some(b'*')) => {
+                    depth += 1;
+                    self.advance(pos, 2);
                 }
-                _ => {
-                    self.advance(&mut pos, 1);
+                (b'*', Some(b'/')) => {
+                    depth -= 1;
+                    self.advance(pos, 2);
+                    if depth == 0 {
+                        return Ok(());
+                    }
                 }
+                _ => self.advance(pos, 1),
             }
         }
+
+        Err(CompilerError::new(
+            ErrorKind::UnclosedComment,
+            Span::new(start_pos, start_pos + 2),
+            self.source,
+            self.filename.clone(),
+        ))
     }
 
-    fn skip_line_comment(&mut self) {
-        let mut pos = self.pos;
+    fn skip_line_comment(&self, pos: &mut usize) {
         let input_len = self.input_len;
 
-        while likely(pos < input_len) {
-            match self.peek_unlocked(&pos) {
+        while likely(*pos < input_len) {
+            match self.peek_unlocked(pos) {
                 b'\n' => break,
                 _ => {
-                    self.advance(&mut pos, 1);
+                    self.advance(pos, 1);
                 }
             }
         }
     }
 }
+
+// Yields every token up to and including `Eof`, with an `Err` in place of
+// each stretch of input that could not be lexed.
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, CompilerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if unlikely(self.finished) {
+            return None;
+        }
+
+        let result = self.next_token();
+        self.finished = matches!(&result, Ok(token) if token.token_type == TokenType::Eof);
+        Some(result)
+    }
+}
+
+// Decodes the escape sequence at the start of `bytes`, which begins with `\`.
+// On success returns the character and the length of the sequence, otherwise
+// the length of the invalid part.
+pub fn decode_escape(bytes: &[u8]) -> Result<(char, usize), usize> {
+    let hex_digits = |from: usize, max: usize| {
+        bytes[from.min(bytes.len())..]
+            .iter()
+            .take(max)
+            .take_while(|b| (CHAR_TABLE[**b as usize] & CHAR_HEX_DIGIT) != 0)
+            .count()
+    };
+    let hex_value = |digits: &[u8]| {
+        digits.iter().fold(0u32, |acc, b| {
+            (acc << 4) | (*b as char).to_digit(16).unwrap()
+        })
+    };
+
+    match bytes.get(1) {
+        Some(b'n') => Ok(('\n', 2)),
+        Some(b't') => Ok(('\t', 2)),
+        Some(b'r') => Ok(('\r', 2)),
+        Some(b'0') => Ok(('\0', 2)),
+        Some(b'\\') => Ok(('\\', 2)),
+        Some(b'"') => Ok(('"', 2)),
+        Some(b'\'') => Ok(('\'', 2)),
+        // `\x` only covers ASCII so that it always encodes a whole character.
+        Some(b'x') => {
+            let count = hex_digits(2, 2);
+            if unlikely(count < 2) {
+                return Err(2 + count);
+            }
+            match hex_value(&bytes[2..4]) {
+                value @ 0..=0x7F => Ok((value as u8 as char, 4)),
+                _ => Err(4),
+            }
+        }
+        Some(b'u') => {
+            if unlikely(bytes.get(2) != Some(&b'{')) {
+                return Err(2);
+            }
+            let count = hex_digits(3, 6);
+            let end = 3 + count;
+            if unlikely(count == 0 || bytes.get(end) != Some(&b'}')) {
+                return Err(end);
+            }
+            match char::from_u32(hex_value(&bytes[3..end])) {
+                Some(c) => Ok((c, end + 1)),
+                None => Err(end + 1),
+            }
+        }
+        Some(b'\n') | None => Err(1),
+        // Cover the whole offending character, which may be multi-byte.
+        Some(b) => Err(1 + utf8_len(*b)),
+    }
+}
+
+// Length of the UTF-8 sequence introduced by `b`.
+#[inline(always)]
+fn utf8_len(b: u8) -> usize {
+    match b {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xFF => 4,
+        _ => 1,
+    }
+}
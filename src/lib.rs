@@ -1,12 +1,25 @@
+#[macro_use]
 pub mod ast;
 pub mod error;
+pub mod interpreter;
 pub mod lexer;
 pub mod parser;
+pub mod resolver;
 pub mod span;
+pub mod symbol;
 pub mod token;
+pub mod typeck;
+pub mod value;
+pub mod visit;
 
 pub use error::{CompilerError, ErrorKind};
+pub use interpreter::{Environment, Interpreter};
 pub use lexer::Lexer;
 pub use parser::Parser;
+pub use resolver::{Globals, Resolver};
 pub use span::Span;
+pub use symbol::Symbol;
 pub use token::{Token, TokenType};
+pub use typeck::{TypeChecker, TypeEnv};
+pub use value::Value;
+pub use visit::{Fold, Visitor, VisitorMut};
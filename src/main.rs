@@ -2,9 +2,12 @@ use memmap2::Mmap;
 use std::fs::File;
 use std::io::{self, BufRead, Write};
 use std::path::Path;
+use std::rc::Rc;
 use std::time::Instant;
 
-use regula_project::{Lexer, Parser, Token, TokenType};
+use regula_project::{
+    Environment, Globals, Interpreter, Lexer, Parser, Resolver, TypeChecker, TypeEnv, Value,
+};
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
@@ -31,6 +34,9 @@ fn main() {
 fn run_repl(verbose: bool) {
     let stdin = io::stdin();
     let mut stdout = io::stdout();
+    let mut env = Environment::new();
+    let mut globals = Globals::new();
+    let mut types = TypeEnv::new();
     println!("Regula REPL (type 'exit' to exit)");
 
     loop {
@@ -49,7 +55,7 @@ fn run_repl(verbose: bool) {
             continue;
         }
 
-        execute(line, verbose);
+        execute(line, &mut env, &mut globals, &mut types, false, verbose);
     }
     println!("Goodbye!")
 }
@@ -80,41 +86,68 @@ fn run_file(filename: &str, verbose: bool) {
         }
     };
 
-    execute(source, verbose);
+    let mut env = Environment::new();
+    let mut globals = Globals::new();
+    let mut types = TypeEnv::new();
+    execute(source, &mut env, &mut globals, &mut types, true, verbose);
 }
 
-fn execute(source: &str, verbose: bool) {
+fn execute(
+    source: &str,
+    env: &mut Environment,
+    globals: &mut Globals,
+    types: &mut TypeEnv,
+    call_main: bool,
+    verbose: bool,
+) {
     let start = Instant::now();
 
-    let source_bytes = source.as_bytes();
-    let mut lexer = Lexer::new(String::from("<stdin>"), source_bytes);
-    let mut tokens: Vec<Token> = vec![];
-    loop {
-        let token = lexer.next_token();
-        match token {
-            Ok(tok) => {
-                if tok.token_type == TokenType::Eof {
-                    tokens.push(tok);
-                    break;
-                }
-
-                tokens.push(tok);
-            }
-            Err(e) => {
+    let source_bytes: Rc<[u8]> = Rc::from(source.as_bytes());
+    let lexer = Lexer::new(String::from("<stdin>"), &source_bytes);
+    let mut parser = Parser::from_lexer(lexer, &source_bytes, String::from("<stdin>"));
+    let mut ast = match parser.parse() {
+        Ok(ast) => ast,
+        Err(errors) => {
+            for e in errors {
                 eprintln!("{}", e);
-                return;
             }
+            return;
         }
+    };
+
+    if verbose {
+        println!("{:#?}", ast)
     }
 
-    let mut parser = Parser::new(&tokens, source_bytes, String::from("<stdin>"));
-    let ast = parser.parse();
-    match ast {
-        Ok(ast) => {
-            if !verbose {
-                println!("{:#?}", ast)
-            }
-        }
+    // A rejected program must not leave its globals behind in the REPL.
+    let snapshot = globals.clone();
+    let mut resolver = Resolver::new(globals, &source_bytes, String::from("<stdin>"));
+    if let Err(e) = resolver.resolve(&mut ast) {
+        *globals = snapshot;
+        eprintln!("{}", e);
+        return;
+    }
+
+    let mut checker = TypeChecker::new(types, &source_bytes, String::from("<stdin>"));
+    if let Err(e) = checker.check(&ast) {
+        *globals = snapshot;
+        eprintln!("{}", e);
+        return;
+    }
+
+    let mut interpreter = Interpreter::new(env, &source_bytes, String::from("<stdin>"));
+    let mut result = interpreter.run(&Rc::new(ast));
+    if call_main
+        && result.is_ok()
+        && let Some(main) = globals.lookup("main")
+        && let Some(main_result) = interpreter.call_global(main)
+    {
+        result = main_result;
+    }
+
+    match result {
+        Ok(Value::None) => {}
+        Ok(value) => println!("{}", value),
         Err(e) => {
             eprintln!("{}", e);
             return;
@@ -1,86 +1,295 @@
 use crate::Span;
-use crate::ast::{BinOp, Expr, Program, Type};
+use crate::ast::{Ast, BinOp, Expr, NodeId, NodeMap, Param, Program, Type};
 use crate::ast::{Literal, UnaryOp};
-use crate::error::{CompilerError, ErrorKind};
+use crate::error::{CompilerError, ErrorKind, MAX_ERRORS, push_error};
+use crate::lexer::{Lexer, decode_escape};
+use crate::symbol::Symbol;
 use crate::token::{Token, TokenType};
-use branches::likely;
+use branches::{likely, unlikely};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+enum Tokens<'a> {
+    Slice(&'a [Token]),
+    // Only the previous, current and next two tokens are kept, which covers
+    // all the lookahead the grammar needs. Lexer errors are skipped over and
+    // reported once parsing stops.
+    Stream {
+        lexer: Box<Lexer<'a>>,
+        window: VecDeque<Token>,
+        errors: Vec<CompilerError>,
+    },
+}
 
 pub struct Parser<'a> {
-    tokens: &'a [Token],
-    input: &'a [u8],
+    tokens: Tokens<'a>,
+    input: &'a Rc<[u8]>,
     filename: String,
+    // Nodes are allocated here as soon as they become a child of another node.
+    ast: Ast,
+    // Parse errors with the start of the statement they were found in.
+    errors: Vec<(usize, CompilerError)>,
     pos: usize,
+    // Delimiters opened and not closed yet, for recovering after an error.
+    depth: usize,
+    func_depth: usize,
+    // Keyword of every enclosing loop, innermost last.
+    loops: Vec<TokenType>,
 }
 
 impl<'a> Parser<'a> {
     #[inline(always)]
-    pub fn new(tokens: &'a [Token], input: &'a [u8], filename: String) -> Self {
+    pub fn new(tokens: &'a [Token], input: &'a Rc<[u8]>, filename: String) -> Self {
+        Self::with_tokens(Tokens::Slice(tokens), input, filename)
+    }
+
+    // Pulls tokens from `lexer` as parsing goes instead of lexing everything first.
+    pub fn from_lexer(lexer: Lexer<'a>, input: &'a Rc<[u8]>, filename: String) -> Self {
+        let mut tokens = Tokens::Stream {
+            lexer: Box::new(lexer),
+            window: VecDeque::with_capacity(4),
+            errors: Vec::new(),
+        };
+        tokens.pull();
+        tokens.pull();
+        tokens.pull();
+        Self::with_tokens(tokens, input, filename)
+    }
+
+    #[inline(always)]
+    fn with_tokens(tokens: Tokens<'a>, input: &'a Rc<[u8]>, filename: String) -> Self {
         Self {
             tokens,
             input,
             filename,
+            ast: Ast::new(),
+            errors: Vec::new(),
             pos: 0,
+            depth: 0,
+            func_depth: 0,
+            loops: Vec::new(),
+        }
+    }
+
+    // Lexer and parse errors are reported together, in source order. A parse
+    // error in a statement that also has a lexer error is left out, since the
+    // tokens the lexer dropped most likely caused it.
+    pub fn parse(&mut self) -> Result<Program, Vec<CompilerError>> {
+        let program = self.parse_program();
+        let mut errors = self.tokens.finish();
+        if likely(errors.is_empty() && self.errors.is_empty()) {
+            return Ok(program);
         }
+
+        let parse_errors: Vec<_> = std::mem::take(&mut self.errors)
+            .into_iter()
+            .filter(|(start, e)| {
+                !errors
+                    .iter()
+                    .any(|lexed| (*start..=e.span.end).contains(&lexed.span.start))
+            })
+            .map(|(_, e)| e)
+            .collect();
+        let omitted = errors.pop_if(|e| matches!(e.kind, ErrorKind::TooManyErrors { .. }));
+        errors.extend(parse_errors);
+        errors.sort_by_key(|e| e.span.start);
+        errors.extend(omitted);
+        Err(errors)
     }
 
-    pub fn parse(&mut self) -> Result<Program, CompilerError> {
+    fn parse_program(&mut self) -> Program {
         let start = self.peek_unlocked().span;
+        let doc = self
+            .parse_doc(TokenType::InnerDocComment)
+            .map(|(doc, _)| doc);
         let mut exprs = Vec::new();
+        // Statements start where the previous one ended, so that tokens the
+        // lexer dropped in between count as part of them.
+        let mut stmt_start = 0;
 
         while likely(self.peek_kind() != TokenType::Eof) {
-            exprs.push(self.parse_expr()?);
-            if self.eat(TokenType::Semicolon).is_none() {
-                return Err(CompilerError::new(
-                    ErrorKind::MissingSemicolon,
-                    self.peek_unlocked().span,
-                    self.input,
-                    self.filename.clone(),
-                ));
-            };
+            match self.parse_top_level() {
+                Ok(expr) => exprs.push(expr),
+                Err(e) => {
+                    self.errors.push((stmt_start, e));
+                    if unlikely(self.errors.len() >= MAX_ERRORS) {
+                        break;
+                    }
+                    self.synchronize();
+                }
+            }
+            stmt_start = self.prev_span().end;
         }
 
-        let span = start.merge(self.eat(TokenType::Eof).unwrap().span);
-        Ok(Program {
-            body: Expr::Block { exprs, span },
+        let span = start.merge(self.peek_unlocked().span);
+        let body = self.ast.alloc(Expr::Block {
+            exprs,
+            tail: false,
+            span,
+        });
+        Program {
+            doc,
+            ast: std::mem::take(&mut self.ast),
+            body,
+            slots: NodeMap::new(),
             filename: self.filename.clone(),
             span,
-        })
+        }
+    }
+
+    fn parse_top_level(&mut self) -> Result<NodeId, CompilerError> {
+        let expr = self.parse_stmt()?;
+        let block_like = expr.is_block_like();
+        if self.eat(TokenType::Semicolon).is_none() && !block_like {
+            return Err(CompilerError::new(
+                ErrorKind::MissingSemicolon,
+                self.peek_unlocked().span,
+                self.input,
+                self.filename.clone(),
+            ));
+        };
+
+        Ok(self.ast.alloc(expr))
+    }
+
+    // Skips to the end of the statement an error was found in: past its `;`,
+    // or past the `}` that closes its outermost block and a `;` right after it.
+    #[cold]
+    fn synchronize(&mut self) {
+        loop {
+            match self.peek_kind() {
+                TokenType::Eof => break,
+                TokenType::Semicolon if self.depth == 0 => {
+                    self.advance();
+                    break;
+                }
+                TokenType::RBrace if self.depth <= 1 => {
+                    self.advance();
+                    self.eat(TokenType::Semicolon);
+                    break;
+                }
+                _ => self.advance(),
+            }
+        }
+
+        self.depth = 0;
+        self.func_depth = 0;
+        self.loops.clear();
+    }
+
+    // A block-like expression that starts a statement ends there instead of
+    // becoming an operand, so `if` in this position may omit `else`.
+    // Functions have no value, so they may only be declared here.
+    fn parse_stmt(&mut self) -> Result<Expr, CompilerError> {
+        match self.peek_kind() {
+            TokenType::DocComment => self.parse_documented(),
+            TokenType::InnerDocComment => Err(CompilerError::new(
+                ErrorKind::DanglingDocComment,
+                self.peek_unlocked().span,
+                self.input,
+                self.filename.clone(),
+            )),
+            TokenType::If => self.parse_if(false),
+            TokenType::LBrace => self.parse_block(),
+            TokenType::While => self.parse_while(),
+            TokenType::Loop => self.parse_loop(),
+            TokenType::For => self.parse_for(),
+            TokenType::Func => self.parse_func(),
+            _ => self.parse_expr(),
+        }
+    }
+
+    fn parse_documented(&mut self) -> Result<Expr, CompilerError> {
+        let (text, span) = self.parse_doc(TokenType::DocComment).unwrap();
+        if unlikely(!matches!(
+            self.peek_kind(),
+            TokenType::Let | TokenType::Const | TokenType::Func
+        )) {
+            return Err(CompilerError::new(
+                ErrorKind::DanglingDocComment,
+                span,
+                self.input,
+                self.filename.clone(),
+            ));
+        }
+
+        let mut expr = self.parse_stmt()?;
+        if let Expr::Let { doc, .. } | Expr::Const { doc, .. } | Expr::Func { doc, .. } = &mut expr
+        {
+            *doc = Some(text);
+        }
+        Ok(expr)
+    }
+
+    // Joins consecutive doc comment lines, dropping the marker and one space.
+    fn parse_doc(&mut self, kind: TokenType) -> Option<(String, Span)> {
+        let start = self.peek_unlocked().span;
+        let mut lines = Vec::new();
+        while let Some(tok) = self.eat(kind) {
+            let line = std::str::from_utf8(&self.slice(&tok.span)[3..]).unwrap();
+            lines.push(line.strip_prefix(' ').unwrap_or(line));
+        }
+
+        if lines.is_empty() {
+            return None;
+        }
+        Some((lines.join("\n"), start.merge(self.prev_span())))
     }
 
     fn parse_expr(&mut self) -> Result<Expr, CompilerError> {
         match self.peek_kind() {
             TokenType::Let => self.parse_let(),
             TokenType::Const => self.parse_const(),
-            _ => self.parse_or(),
+            TokenType::Return => self.parse_return(),
+            TokenType::Break => self.parse_break(),
+            TokenType::Continue => self.parse_continue(),
+            _ => self.parse_assign(),
         }
     }
 
     fn parse_let(&mut self) -> Result<Expr, CompilerError> {
         let start = self.eat(TokenType::Let).unwrap().span;
 
-        let target = self.parse_expr()?;
+        let mutable = self.eat(TokenType::Mut).is_some();
+        let target = self.parse_ident()?;
         let mut end = *target.span();
         let mut type_ = Type::Auto;
+        let mut kind_span = None;
 
         if self.eat(TokenType::Colon).is_some() {
+            let type_start = self.peek_unlocked().span;
             type_ = self.parse_type()?;
-            end = self.peek_unlocked().span;
+            end = self.prev_span();
+            kind_span = Some(type_start.merge(end));
         }
 
+        // Without `mut` the binding could never be given a value later.
         let init = if self.eat(TokenType::Assign).is_none() {
+            if unlikely(!mutable) {
+                return Err(CompilerError::new(
+                    ErrorKind::MissingAssignment,
+                    self.peek_unlocked().span,
+                    self.input,
+                    self.filename.clone(),
+                )
+                .with_note(*target.span(), "declared here without `mut`"));
+            }
             None
         } else {
             let expr = self.parse_expr()?;
-            Some(Box::new(expr))
+            Some(self.ast.alloc(expr))
         };
 
-        if let Some(init_expr) = &init {
-            end = *init_expr.span();
+        if let Some(init_expr) = init {
+            end = self.ast.span(init_expr);
         }
 
         Ok(Expr::Let {
-            target: Box::new(target),
+            doc: None,
+            target: self.ast.alloc(target),
+            mutable,
             kind: type_,
+            kind_span,
             init,
             span: start.merge(end),
         })
@@ -89,11 +298,11 @@ impl<'a> Parser<'a> {
     fn parse_const(&mut self) -> Result<Expr, CompilerError> {
         let start = self.eat(TokenType::Const).unwrap().span;
 
-        let target = self.parse_expr()?;
-        let type_;
-
-        if self.eat(TokenType::Colon).is_some() {
-            type_ = self.parse_type()?;
+        let target = self.parse_ident()?;
+        let (type_, kind_span) = if self.eat(TokenType::Colon).is_some() {
+            let type_start = self.peek_unlocked().span;
+            let type_ = self.parse_type()?;
+            (type_, type_start.merge(self.prev_span()))
         } else {
             return Err(CompilerError::new(
                 ErrorKind::MissingTypeAnnotation,
@@ -101,7 +310,7 @@ impl<'a> Parser<'a> {
                 self.input,
                 self.filename.clone(),
             ));
-        }
+        };
 
         if self.eat(TokenType::Assign).is_none() {
             return Err(CompilerError::new(
@@ -116,19 +325,398 @@ impl<'a> Parser<'a> {
         let end = *expr.span();
 
         Ok(Expr::Const {
-            target: Box::new(target),
+            doc: None,
+            target: self.ast.alloc(target),
             kind: type_,
-            value: Box::new(expr),
+            kind_span,
+            value: self.ast.alloc(expr),
+            span: start.merge(end),
+        })
+    }
+
+    fn parse_func(&mut self) -> Result<Expr, CompilerError> {
+        let start = self.eat(TokenType::Func).unwrap().span;
+        let name_span = self.peek_unlocked().span;
+        let name = self.expect_ident()?;
+
+        let left_paren_span = self.peek_unlocked().span;
+        if self.eat(TokenType::LParen).is_none() {
+            return Err(CompilerError::new(
+                ErrorKind::InvalidSyntax,
+                left_paren_span,
+                self.input,
+                self.filename.clone(),
+            ));
+        }
+
+        let mut params = Vec::new();
+        while self.peek_kind() != TokenType::RParen {
+            let param_start = self.peek_unlocked().span;
+            let mutable = self.eat(TokenType::Mut).is_some();
+            let name_span = self.peek_unlocked().span;
+            let param_name = self.expect_ident()?;
+            if self.eat(TokenType::Colon).is_none() {
+                return Err(CompilerError::new(
+                    ErrorKind::MissingTypeAnnotation,
+                    self.peek_unlocked().span,
+                    self.input,
+                    self.filename.clone(),
+                ));
+            }
+            let kind = self.parse_type()?;
+            let param_end = self.prev_span();
+
+            params.push(Param {
+                name: param_name,
+                name_span,
+                mutable,
+                kind,
+                span: param_start.merge(param_end),
+            });
+
+            if self.eat(TokenType::Comma).is_none() {
+                break;
+            }
+        }
+
+        if self.eat(TokenType::RParen).is_none() {
+            return Err(CompilerError::new(
+                ErrorKind::UnclosedParenthesis,
+                left_paren_span,
+                self.input,
+                self.filename.clone(),
+            ));
+        }
+
+        let (ret, ret_span) = if self.eat(TokenType::Arrow).is_some() {
+            let type_start = self.peek_unlocked().span;
+            let ret = self.parse_type()?;
+            (ret, Some(type_start.merge(self.prev_span())))
+        } else {
+            (Type::None, None)
+        };
+
+        // `break` and `continue` cannot cross a function boundary.
+        let loops = std::mem::take(&mut self.loops);
+        self.func_depth += 1;
+        let body = self.parse_block();
+        self.func_depth -= 1;
+        self.loops = loops;
+        let body = body?;
+        let end = *body.span();
+
+        Ok(Expr::Func {
+            doc: None,
+            name,
+            name_span,
+            params,
+            ret,
+            ret_span,
+            body: self.ast.alloc(body),
+            span: start.merge(end),
+        })
+    }
+
+    fn parse_return(&mut self) -> Result<Expr, CompilerError> {
+        let start = self.eat(TokenType::Return).unwrap().span;
+
+        if unlikely(self.func_depth == 0) {
+            return Err(CompilerError::new(
+                ErrorKind::ReturnOutsideFunction,
+                start,
+                self.input,
+                self.filename.clone(),
+            ));
+        }
+
+        let value = if self.at_expr_end() {
+            None
+        } else {
+            let value = self.parse_expr()?;
+            Some(self.ast.alloc(value))
+        };
+
+        let end = match value {
+            Some(value) => self.ast.span(value),
+            None => start,
+        };
+
+        Ok(Expr::Return {
+            value,
+            span: start.merge(end),
+        })
+    }
+
+    fn parse_if(&mut self, as_value: bool) -> Result<Expr, CompilerError> {
+        let start = self.eat(TokenType::If).unwrap().span;
+        let cond = self.parse_expr()?;
+        let then_branch = self.parse_block()?;
+        let mut end = *then_branch.span();
+
+        let else_branch = if self.eat(TokenType::Else).is_some() {
+            let branch = if self.peek_kind() == TokenType::If {
+                self.parse_if(as_value)?
+            } else {
+                self.parse_block()?
+            };
+            end = *branch.span();
+            Some(self.ast.alloc(branch))
+        } else {
+            None
+        };
+
+        let span = start.merge(end);
+        if unlikely(as_value && else_branch.is_none()) {
+            return Err(CompilerError::new(
+                ErrorKind::MissingElse,
+                span,
+                self.input,
+                self.filename.clone(),
+            ));
+        }
+
+        Ok(Expr::If {
+            cond: self.ast.alloc(cond),
+            then_branch: self.ast.alloc(then_branch),
+            else_branch,
+            span,
+        })
+    }
+
+    fn parse_while(&mut self) -> Result<Expr, CompilerError> {
+        let start = self.eat(TokenType::While).unwrap().span;
+        let cond = self.parse_expr()?;
+        let body = self.parse_loop_body(TokenType::While)?;
+        let end = *body.span();
+
+        Ok(Expr::While {
+            cond: self.ast.alloc(cond),
+            body: self.ast.alloc(body),
+            span: start.merge(end),
+        })
+    }
+
+    fn parse_loop(&mut self) -> Result<Expr, CompilerError> {
+        let start = self.eat(TokenType::Loop).unwrap().span;
+        let body = self.parse_loop_body(TokenType::Loop)?;
+        let end = *body.span();
+
+        Ok(Expr::Loop {
+            body: self.ast.alloc(body),
+            span: start.merge(end),
+        })
+    }
+
+    fn parse_for(&mut self) -> Result<Expr, CompilerError> {
+        let start = self.eat(TokenType::For).unwrap().span;
+
+        // `for name in iter { ... }`
+        if self.peek_kind() == TokenType::Identifier && self.peek_kind_at(1) == TokenType::In {
+            let var = self.parse_ident()?;
+            self.advance();
+            let iter = self.parse_expr()?;
+            let body = self.parse_loop_body(TokenType::For)?;
+            let end = *body.span();
+
+            return Ok(Expr::ForIn {
+                var: self.ast.alloc(var),
+                iter: self.ast.alloc(iter),
+                body: self.ast.alloc(body),
+                span: start.merge(end),
+            });
+        }
+
+        // `for init; cond; step { ... }`, every clause is optional.
+        let init = self.parse_for_clause(TokenType::Semicolon)?;
+        self.expect_semicolon()?;
+        let cond = self.parse_for_clause(TokenType::Semicolon)?;
+        self.expect_semicolon()?;
+        let step = self.parse_for_clause(TokenType::LBrace)?;
+        let body = self.parse_loop_body(TokenType::For)?;
+        let end = *body.span();
+
+        Ok(Expr::For {
+            init,
+            cond,
+            step,
+            body: self.ast.alloc(body),
+            span: start.merge(end),
+        })
+    }
+
+    fn parse_for_clause(&mut self, end: TokenType) -> Result<Option<NodeId>, CompilerError> {
+        if self.peek_kind() == end {
+            Ok(None)
+        } else {
+            let expr = self.parse_expr()?;
+            Ok(Some(self.ast.alloc(expr)))
+        }
+    }
+
+    fn parse_loop_body(&mut self, kind: TokenType) -> Result<Expr, CompilerError> {
+        self.loops.push(kind);
+        let body = self.parse_block();
+        self.loops.pop();
+        body
+    }
+
+    fn parse_break(&mut self) -> Result<Expr, CompilerError> {
+        let start = self.eat(TokenType::Break).unwrap().span;
+
+        let innermost = match self.loops.last() {
+            Some(kind) => *kind,
+            None => {
+                return Err(CompilerError::new(
+                    ErrorKind::OutsideLoop,
+                    start,
+                    self.input,
+                    self.filename.clone(),
+                ));
+            }
+        };
+
+        let value = if self.at_expr_end() {
+            None
+        } else {
+            let value = self.parse_expr()?;
+            Some(self.ast.alloc(value))
+        };
+
+        let end = match value {
+            Some(value) => self.ast.span(value),
+            None => start,
+        };
+
+        let span = start.merge(end);
+        if unlikely(value.is_some() && innermost != TokenType::Loop) {
+            return Err(CompilerError::new(
+                ErrorKind::BreakWithValue,
+                span,
+                self.input,
+                self.filename.clone(),
+            ));
+        }
+
+        Ok(Expr::Break { value, span })
+    }
+
+    fn parse_continue(&mut self) -> Result<Expr, CompilerError> {
+        let span = self.eat(TokenType::Continue).unwrap().span;
+
+        if unlikely(self.loops.is_empty()) {
+            return Err(CompilerError::new(
+                ErrorKind::OutsideLoop,
+                span,
+                self.input,
+                self.filename.clone(),
+            ));
+        }
+
+        Ok(Expr::Continue { span })
+    }
+
+    fn parse_array(&mut self) -> Result<Expr, CompilerError> {
+        let start = self.eat(TokenType::LBracket).unwrap().span;
+
+        let mut elements = Vec::new();
+        while self.peek_kind() != TokenType::RBracket {
+            let element = self.parse_expr()?;
+            elements.push(self.ast.alloc(element));
+            if self.eat(TokenType::Comma).is_none() {
+                break;
+            }
+        }
+
+        match self.eat(TokenType::RBracket) {
+            Some(tok) => Ok(Expr::Array {
+                elements,
+                span: start.merge(tok.span),
+            }),
+            None => Err(CompilerError::new(
+                ErrorKind::UnclosedBracket,
+                start,
+                self.input,
+                self.filename.clone(),
+            )),
+        }
+    }
+
+    fn parse_block(&mut self) -> Result<Expr, CompilerError> {
+        let start = self.peek_unlocked().span;
+        if self.eat(TokenType::LBrace).is_none() {
+            return Err(CompilerError::new(
+                ErrorKind::ExpectedBlock,
+                start,
+                self.input,
+                self.filename.clone(),
+            ));
+        }
+
+        let mut exprs = Vec::new();
+        let mut tail = false;
+        while self.peek_kind() != TokenType::RBrace {
+            if unlikely(self.peek_kind() == TokenType::Eof) {
+                return Err(CompilerError::new(
+                    ErrorKind::UnclosedBrace,
+                    start,
+                    self.input,
+                    self.filename.clone(),
+                ));
+            }
+
+            let expr = self.parse_stmt()?;
+            let block_like = expr.is_block_like();
+            let declaration = matches!(expr, Expr::Let { .. } | Expr::Const { .. });
+            exprs.push(self.ast.alloc(expr));
+
+            // Like Rust, the last expression is the block's value unless it is
+            // followed by `;`, and block-like statements may omit the `;`.
+            // Declarations have no value to give, so they always need it.
+            tail = self.eat(TokenType::Semicolon).is_none();
+            if unlikely(
+                tail && !block_like && (declaration || self.peek_kind() != TokenType::RBrace),
+            ) {
+                return Err(CompilerError::new(
+                    ErrorKind::MissingSemicolon,
+                    self.peek_unlocked().span,
+                    self.input,
+                    self.filename.clone(),
+                ));
+            }
+        }
+
+        let end = self.eat(TokenType::RBrace).unwrap().span;
+        Ok(Expr::Block {
+            exprs,
+            tail,
             span: start.merge(end),
         })
     }
 
     fn parse_type(&mut self) -> Result<Type, CompilerError> {
+        if self.peek_kind() == TokenType::LBracket {
+            let left_bracket_span = self.peek_unlocked().span;
+            self.advance();
+            let elem = self.parse_type()?;
+            if self.eat(TokenType::RBracket).is_none() {
+                return Err(CompilerError::new(
+                    ErrorKind::UnclosedBracket,
+                    left_bracket_span,
+                    self.input,
+                    self.filename.clone(),
+                ));
+            }
+
+            return Ok(Type::Array(Box::new(elem)));
+        }
+
         let type_ = match self.peek_kind() {
             TokenType::TypeInt => Ok(Type::Int),
             TokenType::TypeFloat => Ok(Type::Float),
             TokenType::TypeString => Ok(Type::String),
+            TokenType::TypeChar => Ok(Type::Char),
             TokenType::TypeBoolean => Ok(Type::Bool),
+            TokenType::None => Ok(Type::None),
             _ => Err(CompilerError::new(
                 ErrorKind::ExpectedType,
                 self.peek_unlocked().span,
@@ -141,6 +729,48 @@ impl<'a> Parser<'a> {
         type_
     }
 
+    fn parse_assign(&mut self) -> Result<Expr, CompilerError> {
+        let target = self.parse_or()?;
+        let start = *target.span();
+
+        let op = match self.peek_kind() {
+            TokenType::Assign => None,
+            TokenType::PlusAssign => Some(BinOp::Add),
+            TokenType::MinusAssign => Some(BinOp::Sub),
+            TokenType::StarAssign => Some(BinOp::Mul),
+            TokenType::SlashAssign => Some(BinOp::Div),
+            TokenType::ModAssign => Some(BinOp::Mod),
+            TokenType::PowerAssign => Some(BinOp::Pow),
+            TokenType::AmpersandAssign => Some(BinOp::BitAnd),
+            TokenType::PipeAssign => Some(BinOp::BitOr),
+            TokenType::BitwiseXorAssign => Some(BinOp::BitXor),
+            TokenType::BitwiseShlAssign => Some(BinOp::Shl),
+            TokenType::BitwiseShrAssign => Some(BinOp::Shr),
+            _ => return Ok(target),
+        };
+
+        if unlikely(!target.is_place()) {
+            return Err(CompilerError::new(
+                ErrorKind::InvalidAssignmentTarget,
+                start,
+                self.input,
+                self.filename.clone(),
+            ));
+        }
+
+        self.advance();
+        // Assignment is right-associative: `a = b = c` is `a = (b = c)`.
+        let value = self.parse_assign()?;
+        let end = *value.span();
+
+        Ok(Expr::Assign {
+            op,
+            target: self.ast.alloc(target),
+            value: self.ast.alloc(value),
+            span: start.merge(end),
+        })
+    }
+
     fn parse_or(&mut self) -> Result<Expr, CompilerError> {
         let mut left = self.parse_and()?;
         let start = *left.span();
@@ -152,8 +782,8 @@ impl<'a> Parser<'a> {
             let end = *right.span();
             left = Expr::Binary {
                 op,
-                left: Box::new(left),
-                right: Box::new(right),
+                left: self.ast.alloc(left),
+                right: self.ast.alloc(right),
                 span: start.merge(end),
             }
         }
@@ -162,18 +792,188 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_and(&mut self) -> Result<Expr, CompilerError> {
-        let mut left = self.parse_add()?;
+        let mut left = self.parse_equality()?;
         let start = *left.span();
 
         while matches!(self.peek_kind(), TokenType::And) {
             let op = BinOp::And;
+            self.advance();
+            let right = self.parse_equality()?;
+            let end = *right.span();
+            left = Expr::Binary {
+                op,
+                left: self.ast.alloc(left),
+                right: self.ast.alloc(right),
+                span: start.merge(end),
+            }
+        }
+
+        Ok(left)
+    }
+
+    // Equality and comparison operators are non-associative: `a == b == c`
+    // and `a < b < c` are rejected instead of silently comparing a bool.
+    fn parse_equality(&mut self) -> Result<Expr, CompilerError> {
+        let left = self.parse_comparison()?;
+        let start = *left.span();
+
+        if !matches!(self.peek_kind(), TokenType::Eq | TokenType::Ne) {
+            return Ok(left);
+        }
+
+        let op = match self.peek_kind() {
+            TokenType::Eq => BinOp::Eq,
+            TokenType::Ne => BinOp::Neq,
+            _ => unreachable!(),
+        };
+
+        self.advance();
+        let right = self.parse_comparison()?;
+        let end = *right.span();
+
+        if unlikely(matches!(self.peek_kind(), TokenType::Eq | TokenType::Ne)) {
+            return Err(CompilerError::new(
+                ErrorKind::ChainedComparison,
+                self.peek_unlocked().span,
+                self.input,
+                self.filename.clone(),
+            ));
+        }
+
+        Ok(Expr::Binary {
+            op,
+            left: self.ast.alloc(left),
+            right: self.ast.alloc(right),
+            span: start.merge(end),
+        })
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, CompilerError> {
+        let left = self.parse_bit_or()?;
+        let start = *left.span();
+
+        if !matches!(
+            self.peek_kind(),
+            TokenType::Lt | TokenType::Gt | TokenType::LtE | TokenType::GtE
+        ) {
+            return Ok(left);
+        }
+
+        let op = match self.peek_kind() {
+            TokenType::Lt => BinOp::Lt,
+            TokenType::Gt => BinOp::Gt,
+            TokenType::LtE => BinOp::Lte,
+            TokenType::GtE => BinOp::Gte,
+            _ => unreachable!(),
+        };
+
+        self.advance();
+        let right = self.parse_bit_or()?;
+        let end = *right.span();
+
+        if unlikely(matches!(
+            self.peek_kind(),
+            TokenType::Lt | TokenType::Gt | TokenType::LtE | TokenType::GtE
+        )) {
+            return Err(CompilerError::new(
+                ErrorKind::ChainedComparison,
+                self.peek_unlocked().span,
+                self.input,
+                self.filename.clone(),
+            ));
+        }
+
+        Ok(Expr::Binary {
+            op,
+            left: self.ast.alloc(left),
+            right: self.ast.alloc(right),
+            span: start.merge(end),
+        })
+    }
+
+    // Bitwise operators bind tighter than comparisons, so `a & 1 == 0` is
+    // `(a & 1) == 0`.
+    fn parse_bit_or(&mut self) -> Result<Expr, CompilerError> {
+        let mut left = self.parse_bit_xor()?;
+        let start = *left.span();
+
+        while matches!(self.peek_kind(), TokenType::Pipe) {
+            let op = BinOp::BitOr;
+            self.advance();
+            let right = self.parse_bit_xor()?;
+            let end = *right.span();
+            left = Expr::Binary {
+                op,
+                left: self.ast.alloc(left),
+                right: self.ast.alloc(right),
+                span: start.merge(end),
+            }
+        }
+
+        Ok(left)
+    }
+
+    fn parse_bit_xor(&mut self) -> Result<Expr, CompilerError> {
+        let mut left = self.parse_bit_and()?;
+        let start = *left.span();
+
+        while matches!(self.peek_kind(), TokenType::BitwiseXor) {
+            let op = BinOp::BitXor;
+            self.advance();
+            let right = self.parse_bit_and()?;
+            let end = *right.span();
+            left = Expr::Binary {
+                op,
+                left: self.ast.alloc(left),
+                right: self.ast.alloc(right),
+                span: start.merge(end),
+            }
+        }
+
+        Ok(left)
+    }
+
+    fn parse_bit_and(&mut self) -> Result<Expr, CompilerError> {
+        let mut left = self.parse_shift()?;
+        let start = *left.span();
+
+        while matches!(self.peek_kind(), TokenType::Ampersand) {
+            let op = BinOp::BitAnd;
+            self.advance();
+            let right = self.parse_shift()?;
+            let end = *right.span();
+            left = Expr::Binary {
+                op,
+                left: self.ast.alloc(left),
+                right: self.ast.alloc(right),
+                span: start.merge(end),
+            }
+        }
+
+        Ok(left)
+    }
+
+    fn parse_shift(&mut self) -> Result<Expr, CompilerError> {
+        let mut left = self.parse_add()?;
+        let start = *left.span();
+
+        while matches!(
+            self.peek_kind(),
+            TokenType::BitwiseShl | TokenType::BitwiseShr
+        ) {
+            let op = match self.peek_kind() {
+                TokenType::BitwiseShl => BinOp::Shl,
+                TokenType::BitwiseShr => BinOp::Shr,
+                _ => unreachable!(),
+            };
+
             self.advance();
             let right = self.parse_add()?;
             let end = *right.span();
             left = Expr::Binary {
                 op,
-                left: Box::new(left),
-                right: Box::new(right),
+                left: self.ast.alloc(left),
+                right: self.ast.alloc(right),
                 span: start.merge(end),
             }
         }
@@ -197,8 +997,8 @@ impl<'a> Parser<'a> {
             let end = *right.span();
             left = Expr::Binary {
                 op,
-                left: Box::new(left),
-                right: Box::new(right),
+                left: self.ast.alloc(left),
+                right: self.ast.alloc(right),
                 span: start.merge(end),
             }
         }
@@ -226,8 +1026,8 @@ impl<'a> Parser<'a> {
             let end = *right.span();
             left = Expr::Binary {
                 op,
-                left: Box::new(left),
-                right: Box::new(right),
+                left: self.ast.alloc(left),
+                right: self.ast.alloc(right),
                 span: start.merge(end),
             }
         }
@@ -246,8 +1046,8 @@ impl<'a> Parser<'a> {
             let end = *right.span();
             left = Expr::Binary {
                 op,
-                left: Box::new(left),
-                right: Box::new(right),
+                left: self.ast.alloc(left),
+                right: self.ast.alloc(right),
                 span: start.merge(end),
             }
         };
@@ -258,7 +1058,7 @@ impl<'a> Parser<'a> {
     fn parse_unary(&mut self) -> Result<Expr, CompilerError> {
         if matches!(
             self.peek_kind(),
-            TokenType::Plus | TokenType::Minus | TokenType::Not
+            TokenType::Plus | TokenType::Minus | TokenType::Not | TokenType::Tilde
         ) {
             let start = self.peek_unlocked().span;
             let op = match self.peek_kind() {
@@ -266,8 +1066,19 @@ impl<'a> Parser<'a> {
                     self.advance();
                     return self.parse_unary();
                 }
+                TokenType::Minus
+                    if self.peek_kind_at(1) == TokenType::Int
+                        && !matches!(
+                            self.peek_kind_at(2),
+                            TokenType::LBracket | TokenType::LParen | TokenType::Dot
+                        ) =>
+                {
+                    self.advance();
+                    return self.parse_int(Some(start));
+                }
                 TokenType::Minus => UnaryOp::Neg,
                 TokenType::Not => UnaryOp::Not,
+                TokenType::Tilde => UnaryOp::BitNot,
                 _ => unreachable!(),
             };
 
@@ -276,13 +1087,101 @@ impl<'a> Parser<'a> {
             let end = *expr.span();
             return Ok(Expr::Unary {
                 op,
-                expr: Box::new(expr),
+                expr: self.ast.alloc(expr),
                 span: start.merge(end),
             });
         };
 
-        self.parse_primary()
+        self.parse_postfix()
     }
+
+    fn parse_postfix(&mut self) -> Result<Expr, CompilerError> {
+        let mut expr = self.parse_primary()?;
+        let start = *expr.span();
+
+        loop {
+            match self.peek_kind() {
+                TokenType::LBracket => {
+                    let left_bracket_span = self.peek_unlocked().span;
+                    self.advance();
+                    let index = self.parse_expr()?;
+                    let end = match self.eat(TokenType::RBracket) {
+                        Some(tok) => tok.span,
+                        None => {
+                            return Err(CompilerError::new(
+                                ErrorKind::UnclosedBracket,
+                                left_bracket_span,
+                                self.input,
+                                self.filename.clone(),
+                            ));
+                        }
+                    };
+
+                    expr = Expr::Index {
+                        target: self.ast.alloc(expr),
+                        index: self.ast.alloc(index),
+                        span: start.merge(end),
+                    };
+                }
+                TokenType::LParen => {
+                    let left_paren_span = self.peek_unlocked().span;
+                    self.advance();
+
+                    let mut args = Vec::new();
+                    while self.peek_kind() != TokenType::RParen {
+                        let arg = self.parse_expr()?;
+                        args.push(self.ast.alloc(arg));
+                        if self.eat(TokenType::Comma).is_none() {
+                            break;
+                        }
+                    }
+
+                    let end = match self.eat(TokenType::RParen) {
+                        Some(tok) => tok.span,
+                        None => {
+                            return Err(CompilerError::new(
+                                ErrorKind::UnclosedParenthesis,
+                                left_paren_span,
+                                self.input,
+                                self.filename.clone(),
+                            ));
+                        }
+                    };
+
+                    expr = Expr::Call {
+                        callee: self.ast.alloc(expr),
+                        args,
+                        span: start.merge(end),
+                    };
+                }
+                TokenType::Dot => {
+                    self.advance();
+                    let tok = match self.eat(TokenType::Identifier) {
+                        Some(tok) => tok,
+                        None => {
+                            return Err(CompilerError::new(
+                                ErrorKind::ExpectedIdentifier,
+                                self.peek_unlocked().span,
+                                self.input,
+                                self.filename.clone(),
+                            ));
+                        }
+                    };
+                    let name = self.symbol(tok.span);
+
+                    expr = Expr::Field {
+                        target: self.ast.alloc(expr),
+                        name,
+                        span: start.merge(tok.span),
+                    };
+                }
+                _ => break,
+            }
+        }
+
+        Ok(expr)
+    }
+
     fn parse_primary(&mut self) -> Result<Expr, CompilerError> {
         match self.peek_kind() {
             TokenType::LParen => {
@@ -299,8 +1198,16 @@ impl<'a> Parser<'a> {
                     Some(_) => Ok(expr),
                 }
             }
+            TokenType::If => self.parse_if(true),
+            TokenType::While => self.parse_while(),
+            TokenType::Loop => self.parse_loop(),
+            TokenType::For => self.parse_for(),
+            TokenType::LBracket => self.parse_array(),
+            TokenType::LBrace => self.parse_block(),
             TokenType::String => self.parse_string(),
-            TokenType::Int => self.parse_int(),
+            TokenType::Char => self.parse_char(),
+            TokenType::FormatStart => self.parse_format(),
+            TokenType::Int => self.parse_int(None),
             TokenType::Float => self.parse_float(),
             TokenType::Identifier => self.parse_ident(),
             TokenType::True | TokenType::False => self.parse_bool(),
@@ -314,7 +1221,9 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_int(&mut self) -> Result<Expr, CompilerError> {
+    // `minus` is the span of a `-` right in front of the literal, which is
+    // folded into it so that e.g. `-128i8` and the smallest `int` fit.
+    fn parse_int(&mut self, minus: Option<Span>) -> Result<Expr, CompilerError> {
         let token = self.eat(TokenType::Int);
 
         match token {
@@ -325,14 +1234,49 @@ impl<'a> Parser<'a> {
                 self.filename.clone(),
             )),
             Some(tok) => {
-                let bytes = self.slice(&tok.span);
-
-                let s = std::str::from_utf8(bytes).unwrap();
-                let value = s.parse::<i64>().unwrap();
+                let text = std::str::from_utf8(self.slice(&tok.span)).unwrap();
+                let (text, suffix) = text.split_at(text.find(['i', 'u']).unwrap_or(text.len()));
+                let (radix, digits) = match text.get(..2) {
+                    Some("0b" | "0B") => (2, &text[2..]),
+                    Some("0o" | "0O") => (8, &text[2..]),
+                    Some("0x" | "0X") => (16, &text[2..]),
+                    _ => (10, text),
+                };
+                let digits = digits.replace('_', "");
+
+                // Suffixes only narrow the range; every integer is stored as `int`.
+                let max = match suffix {
+                    "i8" => i8::MAX as u64,
+                    "i16" => i16::MAX as u64,
+                    "i32" => i32::MAX as u64,
+                    "u8" => u8::MAX as u64,
+                    "u16" => u16::MAX as u64,
+                    "u32" => u32::MAX as u64,
+                    _ => i64::MAX as u64,
+                };
+                let (limit, span) = match minus {
+                    Some(minus) if suffix.starts_with('u') => (0, minus.merge(tok.span)),
+                    Some(minus) => (max + 1, minus.merge(tok.span)),
+                    None => (max, tok.span),
+                };
+                let value = match u64::from_str_radix(&digits, radix) {
+                    Ok(value) if value <= limit => value as i64,
+                    _ => {
+                        return Err(CompilerError::new(
+                            ErrorKind::IntegerOverflow,
+                            span,
+                            self.input,
+                            self.filename.clone(),
+                        ));
+                    }
+                };
 
                 Ok(Expr::Literal {
-                    lit: Literal::Int(value),
-                    span: tok.span,
+                    lit: Literal::Int(match minus {
+                        Some(_) => value.wrapping_neg(),
+                        None => value,
+                    }),
+                    span,
                 })
             }
         }
@@ -349,10 +1293,28 @@ impl<'a> Parser<'a> {
                 self.filename.clone(),
             )),
             Some(tok) => {
-                let bytes = self.slice(&tok.span);
-
-                let s = std::str::from_utf8(bytes).unwrap();
-                let value = s.parse::<f64>().unwrap();
+                let text = std::str::from_utf8(self.slice(&tok.span)).unwrap();
+                let (text, suffix) = text.split_at(text.find('f').unwrap_or(text.len()));
+                let value = match text.replace('_', "").parse::<f64>() {
+                    Ok(value) if suffix == "f32" => value as f32 as f64,
+                    Ok(value) => value,
+                    Err(_) => {
+                        return Err(CompilerError::new(
+                            ErrorKind::InvalidNumber,
+                            tok.span,
+                            self.input,
+                            self.filename.clone(),
+                        ));
+                    }
+                };
+                if unlikely(value.is_infinite()) {
+                    return Err(CompilerError::new(
+                        ErrorKind::FloatOverflow,
+                        tok.span,
+                        self.input,
+                        self.filename.clone(),
+                    ));
+                }
 
                 Ok(Expr::Literal {
                     lit: Literal::Float(value),
@@ -373,9 +1335,7 @@ impl<'a> Parser<'a> {
                 self.filename.clone(),
             )),
             Some(tok) => {
-                let name = std::str::from_utf8(self.slice(&tok.span))
-                    .unwrap()
-                    .to_string();
+                let name = self.symbol(tok.span);
 
                 Ok(Expr::Ident {
                     name,
@@ -397,8 +1357,15 @@ impl<'a> Parser<'a> {
             )),
             Some(tok) => {
                 let bytes = self.slice(&tok.span);
-                let content_bytes = &bytes[1..bytes.len() - 1];
-                let content = std::str::from_utf8(content_bytes).unwrap().to_string();
+                let content = if bytes[0] == b'r' {
+                    let hashes = bytes[1..].iter().take_while(|b| **b == b'#').count();
+                    let raw = &bytes[hashes + 2..bytes.len() - hashes - 1];
+                    std::str::from_utf8(raw).unwrap().to_string()
+                } else if bytes.len() >= 6 && bytes.starts_with(b"\"\"\"") {
+                    self.unindent(&bytes[3..bytes.len() - 3], tok.span.start + 3)?
+                } else {
+                    self.unescape(&bytes[1..bytes.len() - 1], tok.span.start + 1)?
+                };
 
                 Ok(Expr::Literal {
                     lit: Literal::String(content),
@@ -408,8 +1375,188 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn parse_format(&mut self) -> Result<Expr, CompilerError> {
+        let start = self.eat(TokenType::FormatStart).unwrap().span;
+        let mut parts = Vec::new();
+
+        loop {
+            let token = *self.peek_unlocked();
+            match token.token_type {
+                TokenType::FormatText => {
+                    self.advance();
+                    let content =
+                        self.unescape_format(self.slice(&token.span), token.span.start)?;
+                    parts.push(self.ast.alloc(Expr::Literal {
+                        lit: Literal::String(content),
+                        span: token.span,
+                    }));
+                }
+                TokenType::LBrace => {
+                    self.advance();
+                    let part = self.parse_expr()?;
+                    parts.push(self.ast.alloc(part));
+                    if self.eat(TokenType::RBrace).is_none() {
+                        return Err(CompilerError::new(
+                            ErrorKind::UnclosedBrace,
+                            token.span,
+                            self.input,
+                            self.filename.clone(),
+                        ));
+                    }
+                }
+                TokenType::FormatEnd => {
+                    self.advance();
+                    return Ok(Expr::Format {
+                        parts,
+                        span: start.merge(token.span),
+                    });
+                }
+                _ => {
+                    return Err(CompilerError::new(
+                        ErrorKind::UnclosedString,
+                        start.merge(self.prev_span()),
+                        self.input,
+                        self.filename.clone(),
+                    ));
+                }
+            }
+        }
+    }
+
+    // Like `unescape`, but also collapses the `{{` and `}}` of format strings.
+    fn unescape_format(&self, bytes: &[u8], offset: usize) -> Result<String, CompilerError> {
+        let mut content = String::with_capacity(bytes.len());
+        let mut start = 0;
+        let mut pos = 0;
+
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'{' | b'}' => {
+                    content.push_str(&self.unescape(&bytes[start..pos], offset + start)?);
+                    content.push(bytes[pos] as char);
+                    pos += 2;
+                    start = pos;
+                }
+                b'\\' => pos += decode_escape(&bytes[pos..]).map_or(1, |(_, len)| len),
+                _ => pos += 1,
+            }
+        }
+
+        content.push_str(&self.unescape(&bytes[start..], offset + start)?);
+        Ok(content)
+    }
+
+    fn parse_char(&mut self) -> Result<Expr, CompilerError> {
+        let tok = self.eat(TokenType::Char).unwrap();
+        let bytes = self.slice(&tok.span);
+        let content = self.unescape(&bytes[1..bytes.len() - 1], tok.span.start + 1)?;
+
+        let mut chars = content.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Expr::Literal {
+                lit: Literal::Char(c),
+                span: tok.span,
+            }),
+            (None, _) => Err(CompilerError::new(
+                ErrorKind::EmptyChar,
+                tok.span,
+                self.input,
+                self.filename.clone(),
+            )),
+            _ => Err(CompilerError::new(
+                ErrorKind::MultipleChars,
+                tok.span,
+                self.input,
+                self.filename.clone(),
+            )),
+        }
+    }
+
+    // Drops the line break after the opening quotes and the blank line before
+    // the closing ones, then strips the indentation shared by the other lines.
+    fn unindent(&self, bytes: &[u8], offset: usize) -> Result<String, CompilerError> {
+        let blank = |line: &[u8]| line.iter().all(|b| b.is_ascii_whitespace());
+
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (i, b) in bytes.iter().enumerate() {
+            if *b == b'\n' {
+                lines.push((start, &bytes[start..i]));
+                start = i + 1;
+            }
+        }
+        lines.push((start, &bytes[start..]));
+
+        if lines.len() == 1 {
+            return self.unescape(bytes, offset);
+        }
+        if blank(lines[0].1) {
+            lines.remove(0);
+        }
+        if lines.len() > 1 && blank(lines[lines.len() - 1].1) {
+            lines.pop();
+        }
+
+        let indent = lines
+            .iter()
+            .filter(|(_, line)| !blank(line))
+            .map(|(_, line)| {
+                line.iter()
+                    .take_while(|b| matches!(b, b' ' | b'\t'))
+                    .count()
+            })
+            .min()
+            .unwrap_or(0);
+
+        let mut content = String::with_capacity(bytes.len());
+        for (i, (start, line)) in lines.iter().enumerate() {
+            if i > 0 {
+                content.push('\n');
+            }
+            if !blank(line) {
+                content.push_str(&self.unescape(&line[indent..], offset + start + indent)?);
+            }
+        }
+
+        Ok(content)
+    }
+
+    // Escapes were already validated by the lexer, but tokens may come from elsewhere.
+    fn unescape(&self, bytes: &[u8], offset: usize) -> Result<String, CompilerError> {
+        let mut content = String::with_capacity(bytes.len());
+        let mut start = 0;
+        let mut pos = 0;
+
+        while pos < bytes.len() {
+            if likely(bytes[pos] != b'\\') {
+                pos += 1;
+                continue;
+            }
+
+            content.push_str(std::str::from_utf8(&bytes[start..pos]).unwrap());
+            match decode_escape(&bytes[pos..]) {
+                Ok((c, len)) => {
+                    content.push(c);
+                    pos += len;
+                    start = pos;
+                }
+                Err(len) => {
+                    return Err(CompilerError::new(
+                        ErrorKind::InvalidEscape,
+                        Span::new(offset + pos, offset + pos + len),
+                        self.input,
+                        self.filename.clone(),
+                    ));
+                }
+            }
+        }
+
+        content.push_str(std::str::from_utf8(&bytes[start..]).unwrap());
+        Ok(content)
+    }
+
     fn parse_bool(&mut self) -> Result<Expr, CompilerError> {
-        let token = self.peek_unlocked();
+        let token = *self.peek_unlocked();
 
         match token.token_type {
             TokenType::True | TokenType::False => {
@@ -451,14 +1598,59 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn expect_ident(&mut self) -> Result<Symbol, CompilerError> {
+        match self.eat(TokenType::Identifier) {
+            Some(tok) => Ok(self.symbol(tok.span)),
+            None => Err(CompilerError::new(
+                ErrorKind::ExpectedIdentifier,
+                self.peek_unlocked().span,
+                self.input,
+                self.filename.clone(),
+            )),
+        }
+    }
+
+    fn expect_semicolon(&mut self) -> Result<(), CompilerError> {
+        match self.eat(TokenType::Semicolon) {
+            Some(_) => Ok(()),
+            None => Err(CompilerError::new(
+                ErrorKind::MissingSemicolon,
+                self.peek_unlocked().span,
+                self.input,
+                self.filename.clone(),
+            )),
+        }
+    }
+
+    #[inline(always)]
+    fn at_expr_end(&self) -> bool {
+        matches!(
+            self.peek_kind(),
+            TokenType::Semicolon
+                | TokenType::RBrace
+                | TokenType::RParen
+                | TokenType::RBracket
+                | TokenType::Comma
+                | TokenType::Eof
+        )
+    }
+
     #[inline(always)]
     fn slice(&self, span: &Span) -> &'a [u8] {
         &self.input[span.start..span.end]
     }
 
     #[inline(always)]
-    fn peek_unlocked(&self) -> &'a Token {
-        unsafe { self.tokens.get_unchecked(self.pos) }
+    fn symbol(&self, span: Span) -> Symbol {
+        Symbol::intern(std::str::from_utf8(self.slice(&span)).unwrap())
+    }
+
+    #[inline(always)]
+    fn peek_unlocked(&self) -> &Token {
+        match &self.tokens {
+            Tokens::Slice(tokens) => unsafe { tokens.get_unchecked(self.pos) },
+            Tokens::Stream { window, .. } => &window[window.len() - 3],
+        }
     }
 
     #[inline(always)]
@@ -466,15 +1658,47 @@ impl<'a> Parser<'a> {
         self.peek_unlocked().token_type
     }
 
+    // Looks `n` tokens ahead, at most two, without moving past the trailing `Eof`.
+    #[inline(always)]
+    fn peek_kind_at(&self, n: usize) -> TokenType {
+        match &self.tokens {
+            Tokens::Slice(tokens) => tokens[(self.pos + n).min(tokens.len() - 1)].token_type,
+            Tokens::Stream { window, .. } => {
+                window[(window.len() - 3 + n).min(window.len() - 1)].token_type
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn prev_span(&self) -> Span {
+        match &self.tokens {
+            Tokens::Slice(tokens) => tokens[self.pos.saturating_sub(1)].span,
+            Tokens::Stream { window, .. } => window[window.len().saturating_sub(4)].span,
+        }
+    }
+
     #[inline(always)]
     fn advance(&mut self) {
+        match self.peek_kind() {
+            TokenType::LParen | TokenType::LBracket | TokenType::LBrace => self.depth += 1,
+            TokenType::RParen | TokenType::RBracket | TokenType::RBrace => {
+                self.depth = self.depth.saturating_sub(1)
+            }
+            _ => {}
+        }
         self.pos += 1;
+        if let Tokens::Stream { window, .. } = &mut self.tokens {
+            if window.len() == 4 {
+                window.pop_front();
+            }
+            self.tokens.pull();
+        }
     }
 
     #[inline(always)]
-    fn eat(&mut self, token_type: TokenType) -> Option<&'a Token> {
+    fn eat(&mut self, token_type: TokenType) -> Option<Token> {
         if likely(self.peek_kind() == token_type) {
-            let tok = self.peek_unlocked();
+            let tok = *self.peek_unlocked();
             self.advance();
             Some(tok)
         } else {
@@ -482,3 +1706,47 @@ impl<'a> Parser<'a> {
         }
     }
 }
+
+impl Tokens<'_> {
+    fn pull(&mut self) {
+        if let Tokens::Stream {
+            lexer,
+            window,
+            errors,
+        } = self
+        {
+            let token = match window.back() {
+                Some(last) if last.token_type == TokenType::Eof => *last,
+                _ => loop {
+                    match lexer.next_token() {
+                        Ok(token) => break token,
+                        Err(e) => push_error(errors, e),
+                    }
+                },
+            };
+            window.push_back(token);
+        }
+    }
+
+    // Lexes whatever the parser left unread and returns all lexer errors.
+    fn finish(&mut self) -> Vec<CompilerError> {
+        match self {
+            Tokens::Stream {
+                lexer,
+                window,
+                errors,
+            } => {
+                if window
+                    .back()
+                    .is_some_and(|last| last.token_type != TokenType::Eof)
+                {
+                    for e in lexer.by_ref().filter_map(Result::err) {
+                        push_error(errors, e);
+                    }
+                }
+                std::mem::take(errors)
+            }
+            Tokens::Slice(_) => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use crate::Span;
+use crate::ast::{Ast, Expr, NodeId, NodeMap, Program, Slot};
+use crate::error::{CompilerError, ErrorKind};
+use crate::symbol::Symbol;
+use branches::unlikely;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BindingKind {
+    Let,
+    Const,
+    Func,
+    Param,
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    index: usize,
+    kind: BindingKind,
+    mutable: bool,
+    // `None` once the declaring source is gone, e.g. an earlier REPL line.
+    span: Option<Span>,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    names: HashMap<Symbol, Binding>,
+    // Declaration of every function in `names`, for the bodies nested in it.
+    funcs: HashMap<Symbol, NodeId>,
+    len: usize,
+    // `let`/`const` names declared later in this block, for better diagnostics.
+    pending: HashMap<Symbol, Span>,
+}
+
+// Global bindings outlive a single program so the REPL can refer back to them.
+#[derive(Debug, Clone, Default)]
+pub struct Globals {
+    names: HashMap<Symbol, Binding>,
+    len: usize,
+}
+
+impl Globals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        self.names
+            .get(&Symbol::intern(name))
+            .map(|binding| binding.index)
+    }
+}
+
+pub struct Resolver<'a> {
+    globals: &'a mut Globals,
+    input: &'a Rc<[u8]>,
+    filename: String,
+    // Local scopes of the function being resolved, innermost last.
+    scopes: Vec<Scope>,
+    // Local scopes of every enclosing function, innermost last.
+    enclosing: Vec<Vec<Scope>>,
+    // Top-level `let`/`const` names of this program that are not declared yet.
+    pending: HashMap<Symbol, Span>,
+    slots: NodeMap<Slot>,
+}
+
+impl<'a> Resolver<'a> {
+    #[inline(always)]
+    pub fn new(globals: &'a mut Globals, input: &'a Rc<[u8]>, filename: String) -> Self {
+        Self {
+            globals,
+            input,
+            filename,
+            scopes: Vec::new(),
+            enclosing: Vec::new(),
+            pending: HashMap::new(),
+            slots: NodeMap::new(),
+        }
+    }
+
+    pub fn resolve(&mut self, program: &mut Program) -> Result<(), CompilerError> {
+        for binding in self.globals.names.values_mut() {
+            binding.span = None;
+        }
+
+        let ast = &program.ast;
+        let result = match &ast[program.body] {
+            Expr::Block { exprs, .. } => self.resolve_sequence(ast, exprs),
+            _ => self.resolve_expr(ast, program.body),
+        };
+        program.slots = std::mem::take(&mut self.slots);
+        result
+    }
+
+    fn resolve_expr(&mut self, ast: &Ast, id: NodeId) -> Result<(), CompilerError> {
+        match &ast[id] {
+            Expr::Literal { .. } | Expr::Continue { .. } => Ok(()),
+            Expr::Ident { name, span } => {
+                let slot = self.lookup(*name, *span)?;
+                self.slots.insert(id, slot);
+                Ok(())
+            }
+            Expr::Array { elements, .. }
+            | Expr::Format {
+                parts: elements, ..
+            } => {
+                for element in elements {
+                    self.resolve_expr(ast, *element)?;
+                }
+                Ok(())
+            }
+            Expr::Binary { left, right, .. } => {
+                self.resolve_expr(ast, *left)?;
+                self.resolve_expr(ast, *right)
+            }
+            Expr::Unary { expr, .. } => self.resolve_expr(ast, *expr),
+            Expr::Index { target, index, .. } => {
+                self.resolve_expr(ast, *target)?;
+                self.resolve_expr(ast, *index)
+            }
+            Expr::Field { target, .. } => self.resolve_expr(ast, *target),
+            Expr::Assign {
+                target,
+                value,
+                span,
+                ..
+            } => {
+                self.resolve_expr(ast, *value)?;
+                self.resolve_expr(ast, *target)?;
+                self.check_mutable(ast, *target, *span)
+            }
+            Expr::Block { exprs, .. } => {
+                self.scopes.push(Scope::default());
+                let result = self.resolve_sequence(ast, exprs);
+                self.scopes.pop();
+                result
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.resolve_expr(ast, *cond)?;
+                self.resolve_expr(ast, *then_branch)?;
+                match else_branch {
+                    Some(else_branch) => self.resolve_expr(ast, *else_branch),
+                    None => Ok(()),
+                }
+            }
+            Expr::While { cond, body, .. } => {
+                self.resolve_expr(ast, *cond)?;
+                self.resolve_expr(ast, *body)
+            }
+            Expr::Loop { body, .. } => self.resolve_expr(ast, *body),
+            Expr::For {
+                init,
+                cond,
+                step,
+                body,
+                ..
+            } => {
+                self.scopes.push(Scope::default());
+                let result = self.resolve_for(ast, [*init, *cond, *step], *body);
+                self.scopes.pop();
+                result
+            }
+            Expr::ForIn {
+                var, iter, body, ..
+            } => {
+                self.resolve_expr(ast, *iter)?;
+                self.scopes.push(Scope::default());
+                let result = self
+                    .declare_target(ast, *var, BindingKind::Let, false)
+                    .and_then(|_| self.resolve_expr(ast, *body));
+                self.scopes.pop();
+                result
+            }
+            Expr::Break { value, .. } | Expr::Return { value, .. } => match value {
+                Some(value) => self.resolve_expr(ast, *value),
+                None => Ok(()),
+            },
+            Expr::Func {
+                name,
+                name_span,
+                params,
+                body,
+                ..
+            } => {
+                // Functions in statement position were already hoisted.
+                if self.slots.get(id).is_none() {
+                    self.declare_func(id, *name, *name_span)?;
+                }
+
+                // A body only sees globals, its own parameters and the
+                // functions declared around it.
+                let saved = std::mem::take(&mut self.scopes);
+                self.enclosing.push(saved);
+                self.scopes.push(Scope::default());
+                let mut result = Ok(());
+                for param in params.iter() {
+                    if let Err(e) = self.declare(
+                        param.name,
+                        BindingKind::Param,
+                        param.mutable,
+                        param.name_span,
+                    ) {
+                        result = Err(e);
+                        break;
+                    }
+                }
+                let result = result.and_then(|_| self.resolve_expr(ast, *body));
+                self.scopes = self.enclosing.pop().unwrap();
+                result
+            }
+            Expr::Call { callee, args, .. } => {
+                self.resolve_expr(ast, *callee)?;
+                for arg in args {
+                    self.resolve_expr(ast, *arg)?;
+                }
+                Ok(())
+            }
+            Expr::Let {
+                target,
+                mutable,
+                init,
+                ..
+            } => {
+                if let Some(init) = init {
+                    self.resolve_expr(ast, *init)?;
+                }
+                self.declare_target(ast, *target, BindingKind::Let, *mutable)
+            }
+            Expr::Const { target, value, .. } => {
+                self.resolve_expr(ast, *value)?;
+                self.declare_target(ast, *target, BindingKind::Const, false)
+            }
+        }
+    }
+
+    fn resolve_sequence(&mut self, ast: &Ast, exprs: &[NodeId]) -> Result<(), CompilerError> {
+        // Functions are hoisted so they can be called before their declaration;
+        // other bindings are only remembered to explain a too-early use.
+        for id in exprs {
+            match &ast[*id] {
+                Expr::Func {
+                    name, name_span, ..
+                } => self.declare_func(*id, *name, *name_span)?,
+                Expr::Let { target, .. } | Expr::Const { target, .. } => {
+                    if let Expr::Ident { name, span } = &ast[*target] {
+                        let pending = match self.scopes.last_mut() {
+                            Some(scope) => &mut scope.pending,
+                            None => &mut self.pending,
+                        };
+                        pending.entry(*name).or_insert(*span);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for id in exprs {
+            self.resolve_expr(ast, *id)?;
+        }
+
+        Ok(())
+    }
+
+    fn resolve_for(
+        &mut self,
+        ast: &Ast,
+        clauses: [Option<NodeId>; 3],
+        body: NodeId,
+    ) -> Result<(), CompilerError> {
+        for clause in clauses.into_iter().flatten() {
+            self.resolve_expr(ast, clause)?;
+        }
+
+        self.resolve_expr(ast, body)
+    }
+
+    fn declare_target(
+        &mut self,
+        ast: &Ast,
+        target: NodeId,
+        kind: BindingKind,
+        mutable: bool,
+    ) -> Result<(), CompilerError> {
+        match &ast[target] {
+            Expr::Ident { name, span } => {
+                let slot = self.declare(*name, kind, mutable, *span)?;
+                self.slots.insert(target, slot);
+                Ok(())
+            }
+            expr => Err(self.error(ErrorKind::ExpectedIdentifier, *expr.span())),
+        }
+    }
+
+    fn declare_func(&mut self, id: NodeId, name: Symbol, span: Span) -> Result<(), CompilerError> {
+        let slot = self.declare(name, BindingKind::Func, false, span)?;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.funcs.insert(name, id);
+        }
+        self.slots.insert(id, slot);
+        Ok(())
+    }
+
+    fn declare(
+        &mut self,
+        name: Symbol,
+        kind: BindingKind,
+        mutable: bool,
+        span: Span,
+    ) -> Result<Slot, CompilerError> {
+        if let Some(shadowed) = self.find(name)
+            && unlikely(shadowed.kind == BindingKind::Const)
+        {
+            let error = self.error(ErrorKind::ShadowedConst, span);
+            return Err(match shadowed.span {
+                Some(decl) => error.with_note(decl, "constant declared here"),
+                None => error,
+            });
+        }
+
+        let binding = |index| Binding {
+            index,
+            kind,
+            mutable,
+            span: Some(span),
+        };
+
+        // Shadowing with `let` is fine, but two functions or parameters of the
+        // same name in one scope are ambiguous. Globals left by an earlier REPL
+        // line have no span and may be redefined.
+        let names = match self.scopes.last() {
+            Some(scope) => &scope.names,
+            None => &self.globals.names,
+        };
+        if unlikely(matches!(kind, BindingKind::Func | BindingKind::Param))
+            && let Some(existing) = names.get(&name)
+            && existing.kind == kind
+            && let Some(decl) = existing.span
+        {
+            return Err(self
+                .error(ErrorKind::DuplicateDefinition, span)
+                .with_note(decl, "first defined here"));
+        }
+
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                let index = scope.len;
+                scope.len += 1;
+                scope.pending.remove(&name);
+                scope.names.insert(name, binding(index));
+                Ok(Slot::Local { depth: 0, index })
+            }
+            None => {
+                let index = self.globals.len;
+                self.globals.len += 1;
+                self.pending.remove(&name);
+                self.globals.names.insert(name, binding(index));
+                Ok(Slot::Global(index))
+            }
+        }
+    }
+
+    // Assigning through an index such as `a[0] = 1` mutates `a` itself.
+    fn check_mutable(&self, ast: &Ast, target: NodeId, span: Span) -> Result<(), CompilerError> {
+        let mut base = &ast[target];
+        while let Expr::Index { target, .. } | Expr::Field { target, .. } = base {
+            base = &ast[*target];
+        }
+
+        let binding = match base {
+            Expr::Ident { name, .. } => self.find(*name),
+            _ => None,
+        };
+        match binding {
+            Some(binding) if unlikely(!binding.mutable) => {
+                let error = self.error(ErrorKind::ImmutableAssignment, span);
+                let note = match binding.kind {
+                    BindingKind::Let => "declared here without `mut`",
+                    BindingKind::Param => "parameter declared here without `mut`",
+                    BindingKind::Const => "constant declared here",
+                    BindingKind::Func => "function declared here",
+                };
+                Err(match binding.span {
+                    Some(decl) => error.with_note(decl, note),
+                    None => error,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn find(&self, name: Symbol) -> Option<Binding> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(binding) = scope.names.get(&name) {
+                return Some(binding.clone());
+            }
+        }
+
+        if let Some((binding, _)) = self.find_enclosing(name) {
+            return Some(binding.clone());
+        }
+
+        self.globals.names.get(&name).cloned()
+    }
+
+    // The innermost binding of `name` in an enclosing body, with the
+    // declaration if it is a function.
+    fn find_enclosing(&self, name: Symbol) -> Option<(&Binding, Option<NodeId>)> {
+        self.enclosing
+            .iter()
+            .rev()
+            .flat_map(|scopes| scopes.iter().rev())
+            .find_map(|scope| {
+                let binding = scope.names.get(&name)?;
+                Some((binding, scope.funcs.get(&name).copied()))
+            })
+    }
+
+    fn lookup(&self, name: Symbol, span: Span) -> Result<Slot, CompilerError> {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(binding) = scope.names.get(&name) {
+                return Ok(Slot::Local {
+                    depth,
+                    index: binding.index,
+                });
+            }
+        }
+
+        // Only functions of enclosing bodies are visible, since a body captures
+        // none of their other bindings.
+        if let Some((binding, decl)) = self.find_enclosing(name) {
+            return match decl {
+                Some(decl) if binding.kind == BindingKind::Func => Ok(Slot::Func(decl)),
+                _ => {
+                    let error = self.error(ErrorKind::CapturedLocal, span);
+                    Err(match binding.span {
+                        Some(decl) => error.with_note(decl, "declared here"),
+                        None => error,
+                    })
+                }
+            };
+        }
+
+        if let Some(binding) = self.globals.names.get(&name) {
+            return Ok(Slot::Global(binding.index));
+        }
+
+        let mut pending = self.scopes.iter().rev().map(|scope| &scope.pending);
+        if let Some(decl) = pending
+            .find_map(|pending| pending.get(&name))
+            .or_else(|| self.pending.get(&name))
+        {
+            return Err(self
+                .error(ErrorKind::UseBeforeDeclaration, span)
+                .with_note(*decl, "declared here"));
+        }
+
+        Err(self.error(ErrorKind::UndefinedVariable, span))
+    }
+
+    #[cold]
+    fn error(&self, kind: ErrorKind, span: Span) -> CompilerError {
+        CompilerError::new(kind, span, self.input, self.filename.clone())
+    }
+}
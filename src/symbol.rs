@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{LazyLock, Mutex};
+
+// An interned identifier. Equal names share one symbol, so comparing and
+// hashing names is as cheap as for the index inside.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+// Interned strings are leaked so that `Symbol::as_str` can hand out
+// `&'static str`; a program only has so many distinct names.
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+// One table for the whole process, so symbols can move freely between the
+// parser, later passes and the REPL's next line.
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(Mutex::default);
+
+impl Symbol {
+    pub fn intern(name: &str) -> Self {
+        let mut interner = INTERNER.lock().unwrap();
+        if let Some(symbol) = interner.symbols.get(name) {
+            return *symbol;
+        }
+
+        let symbol = Symbol(interner.strings.len() as u32);
+        let name: &'static str = Box::leak(name.into());
+        interner.strings.push(name);
+        interner.symbols.insert(name, symbol);
+        symbol
+    }
+
+    #[inline(always)]
+    pub fn as_str(self) -> &'static str {
+        INTERNER.lock().unwrap().strings[self.0 as usize]
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -9,11 +9,20 @@ pub enum TokenType {
     Int,
     Float,
     String,
+    Char,
+
+    FormatStart, // f"
+    FormatText,  // text between the expressions of an f-string
+    FormatEnd,   // closing " of an f-string
+
+    DocComment,      // ///
+    InnerDocComment, // //!
 
     TypeInt,     // int
     TypeFloat,   // float
     TypeString,  // str
     TypeBoolean, // bool
+    TypeChar,    // char
 
     Assign, // =
     Plus,   // +
@@ -30,10 +39,16 @@ pub enum TokenType {
     LBracket, // [
     RBracket, // ]
 
-    Comma,     // ,
-    Semicolon, // ;
-    Colon,     // :
-    Dot,       // .
+    Comma,       // ,
+    Semicolon,   // ;
+    Colon,       // :
+    DoubleColon, // ::
+    Dot,         // .
+    DotDot,      // ..
+    DotDotEq,    // ..=
+    Arrow,       // ->
+    FatArrow,    // =>
+    Question,    // ?
 
     Ne,  // !=
     Lt,  // <
@@ -53,6 +68,18 @@ pub enum TokenType {
     BitwiseShl, // <<
     BitwiseShr, // >>
 
+    PlusAssign,       // +=
+    MinusAssign,      // -=
+    StarAssign,       // *=
+    SlashAssign,      // /=
+    ModAssign,        // %=
+    PowerAssign,      // **=
+    AmpersandAssign,  // &=
+    PipeAssign,       // |=
+    BitwiseXorAssign, // ^=
+    BitwiseShlAssign, // <<=
+    BitwiseShrAssign, // >>=
+
     Let,    // let
     Const,  // const
     Struct, // struct
@@ -82,15 +109,24 @@ pub enum TokenType {
     Eof,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
     pub span: Span,
+    // Whitespace and comments around the token; only filled by `Lexer::lossless`.
+    pub leading: Span,
+    pub trailing: Span,
 }
 
 impl Token {
     #[inline(always)]
     pub fn new(token_type: TokenType, span: Span) -> Self {
-        Self { token_type, span }
+        let empty = Span::new(span.start, span.start);
+        Self {
+            token_type,
+            span,
+            leading: empty,
+            trailing: empty,
+        }
     }
 }
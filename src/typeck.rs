@@ -0,0 +1,665 @@
+use crate::Span;
+use crate::ast::{BinOp, Expr, Literal, NodeId, NodeMap, Param, Program, Slot, Type, UnaryOp};
+use crate::error::{CompilerError, ErrorKind};
+use branches::{likely, unlikely};
+use std::rc::Rc;
+
+// Laid out exactly like `Environment`, so resolved slots index it directly.
+#[derive(Debug)]
+pub struct TypeEnv {
+    globals: Vec<Type>,
+    scopes: Vec<Vec<Type>>,
+}
+
+impl Default for TypeEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeEnv {
+    pub fn new() -> Self {
+        Self {
+            globals: Vec::new(),
+            scopes: Vec::new(),
+        }
+    }
+
+    #[inline(always)]
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    #[inline(always)]
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn define(&mut self, slot: Slot, ty: Type) {
+        let (types, index) = match slot {
+            Slot::Global(index) => (&mut self.globals, index),
+            Slot::Local { index, .. } => (self.scopes.last_mut().unwrap(), index),
+            Slot::Func(_) => unreachable!("functions are defined in their own scope"),
+        };
+        if index >= types.len() {
+            types.resize(index + 1, Type::Auto);
+        }
+        types[index] = ty;
+    }
+
+    fn get_mut(&mut self, slot: Slot) -> Option<&mut Type> {
+        match slot {
+            Slot::Global(index) => self.globals.get_mut(index),
+            Slot::Local { depth, index } => {
+                let scope = self.scopes.len().checked_sub(depth + 1)?;
+                self.scopes[scope].get_mut(index)
+            }
+            Slot::Func(_) => None,
+        }
+    }
+
+    // Mirrors `Environment::enter_call`: bodies only see globals and parameters.
+    fn enter_func(&mut self, params: Vec<Type>) -> Vec<Vec<Type>> {
+        std::mem::replace(&mut self.scopes, vec![params])
+    }
+
+    fn exit_func(&mut self, saved: Vec<Vec<Type>>) {
+        self.scopes = saved;
+    }
+}
+
+pub struct TypeChecker<'a> {
+    env: &'a mut TypeEnv,
+    input: &'a Rc<[u8]>,
+    filename: String,
+    // Declared return type of every enclosing function, innermost last.
+    returns: Vec<(Type, Option<Span>)>,
+    // Type produced by the `break`s of every enclosing loop, innermost last.
+    loops: Vec<Type>,
+    types: NodeMap<Type>,
+}
+
+impl<'a> TypeChecker<'a> {
+    #[inline(always)]
+    pub fn new(env: &'a mut TypeEnv, input: &'a Rc<[u8]>, filename: String) -> Self {
+        Self {
+            env,
+            input,
+            filename,
+            returns: Vec::new(),
+            loops: Vec::new(),
+            types: NodeMap::new(),
+        }
+    }
+
+    // Returns the type of every expression in the program.
+    pub fn check(&mut self, program: &Program) -> Result<NodeMap<Type>, CompilerError> {
+        // A rejected program must not leave its globals behind in the REPL.
+        let snapshot = self.env.globals.clone();
+
+        let result = match &program.ast[program.body] {
+            Expr::Block { exprs, .. } => self.check_sequence(program, exprs),
+            _ => self.check_expr(program, program.body),
+        };
+
+        if result.is_err() {
+            self.env.globals = snapshot;
+        }
+        result.map(|_| std::mem::take(&mut self.types))
+    }
+
+    fn check_expr(&mut self, program: &Program, id: NodeId) -> Result<Type, CompilerError> {
+        let ty = self.infer_expr(program, id)?;
+        self.types.insert(id, ty.clone());
+        Ok(ty)
+    }
+
+    fn infer_expr(&mut self, program: &Program, id: NodeId) -> Result<Type, CompilerError> {
+        match &program.ast[id] {
+            Expr::Literal { lit, .. } => Ok(match lit {
+                Literal::Int(_) => Type::Int,
+                Literal::Float(_) => Type::Float,
+                Literal::String(_) => Type::String,
+                Literal::Char(_) => Type::Char,
+                Literal::Bool(_) => Type::Bool,
+                Literal::None => Type::None,
+            }),
+            Expr::Ident { span, .. } => match program.slots.get(id) {
+                Some(Slot::Func(decl)) => match &program.ast[*decl] {
+                    Expr::Func { params, ret, .. } => Ok(func_type(params, ret)),
+                    _ => Err(self.error(ErrorKind::UndefinedVariable, *span)),
+                },
+                Some(slot) => match self.env.get_mut(*slot) {
+                    Some(ty) => Ok(ty.clone()),
+                    None => Err(self.error(ErrorKind::UndefinedVariable, *span)),
+                },
+                None => Err(self.error(ErrorKind::UndefinedVariable, *span)),
+            },
+            Expr::Array { elements, .. } => {
+                let mut elem = Type::Auto;
+                for element in elements {
+                    let ty = self.check_expr(program, *element)?;
+                    self.expect(&elem, &ty, program.ast.span(*element))?;
+                    if elem == Type::Auto {
+                        elem = ty;
+                    }
+                }
+
+                Ok(Type::Array(Box::new(elem)))
+            }
+            Expr::Format { parts, .. } => {
+                for part in parts {
+                    let ty = self.check_expr(program, *part)?;
+                    if unlikely(!displayable(&ty)) {
+                        return Err(self.error(
+                            ErrorKind::NotDisplayable { ty: Box::new(ty) },
+                            program.ast.span(*part),
+                        ));
+                    }
+                }
+
+                Ok(Type::String)
+            }
+            Expr::Binary {
+                op,
+                left,
+                right,
+                span,
+            } => {
+                let left = self.check_expr(program, *left)?;
+                let right = self.check_expr(program, *right)?;
+                self.binary_type(op, left, right, *span)
+            }
+            Expr::Unary { op, expr, span } => {
+                let operand = self.check_expr(program, *expr)?;
+                match (op, &operand) {
+                    (_, Type::Auto | Type::Never) => Ok(Type::Auto),
+                    (UnaryOp::Neg, Type::Int | Type::Float) => Ok(operand),
+                    (UnaryOp::Not, Type::Bool) => Ok(operand),
+                    (UnaryOp::BitNot, Type::Int) => Ok(operand),
+                    _ => Err(self.error(
+                        ErrorKind::UnaryOperandType {
+                            op: op.clone(),
+                            operand: Box::new(operand),
+                        },
+                        *span,
+                    )),
+                }
+            }
+            Expr::Index {
+                target,
+                index,
+                span,
+            } => {
+                let target = self.check_expr(program, *target)?;
+                let index_ty = self.check_expr(program, *index)?;
+                self.expect(&Type::Int, &index_ty, program.ast.span(*index))?;
+                match target {
+                    Type::Array(elem) => Ok(*elem),
+                    Type::Auto | Type::Never => Ok(Type::Auto),
+                    _ => Err(self.error(ErrorKind::InvalidOperand, *span)),
+                }
+            }
+            Expr::Field { target, name, span } => {
+                let ty = self.check_expr(program, *target)?;
+                let name_span = Span::new(span.end - name.as_str().len(), span.end);
+                Err(self.error(ErrorKind::UnknownField { ty: Box::new(ty) }, name_span))
+            }
+            Expr::Assign {
+                op,
+                target,
+                value,
+                span,
+            } => {
+                let target_ty = self.check_expr(program, *target)?;
+                let value_ty = self.check_expr(program, *value)?;
+                let value_ty = match op {
+                    Some(op) => self.binary_type(op, target_ty.clone(), value_ty, *span)?,
+                    None => value_ty,
+                };
+
+                // `let x;` leaves the type open until the first assignment.
+                if target_ty == Type::Auto
+                    && let Expr::Ident { .. } = &program.ast[*target]
+                    && let Some(slot) = program.slots.get(*target)
+                    && let Some(ty) = self.env.get_mut(*slot)
+                {
+                    *ty = value_ty;
+                    return Ok(Type::None);
+                }
+
+                self.expect(&target_ty, &value_ty, program.ast.span(*value))?;
+                Ok(Type::None)
+            }
+            Expr::Block { exprs, tail, .. } => {
+                self.env.push_scope();
+                let result = self.check_sequence(program, exprs);
+                self.env.pop_scope();
+                let last = result?;
+
+                if *tail {
+                    Ok(last)
+                } else if self.diverges(exprs) {
+                    Ok(Type::Never)
+                } else {
+                    Ok(Type::None)
+                }
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+                span,
+            } => {
+                let cond_ty = self.check_expr(program, *cond)?;
+                self.expect(&Type::Bool, &cond_ty, program.ast.span(*cond))?;
+                let then_ty = self.check_expr(program, *then_branch)?;
+
+                match else_branch {
+                    Some(else_branch) => {
+                        let else_ty = self.check_expr(program, *else_branch)?;
+                        if unlikely(!compatible(&then_ty, &else_ty)) {
+                            return Err(self
+                                .mismatch(then_ty, else_ty, program.ast.span(*else_branch))
+                                .with_note(
+                                    program.ast.span(*then_branch),
+                                    "expected because of this",
+                                ));
+                        }
+
+                        Ok(join(then_ty, else_ty))
+                    }
+                    None => {
+                        if unlikely(!compatible(&Type::None, &then_ty)) {
+                            return Err(self.error(ErrorKind::MissingElse, *span));
+                        }
+
+                        Ok(Type::None)
+                    }
+                }
+            }
+            Expr::While { cond, body, .. } => {
+                let cond_ty = self.check_expr(program, *cond)?;
+                self.expect(&Type::Bool, &cond_ty, program.ast.span(*cond))?;
+                self.check_loop_body(program, *body)?;
+                Ok(Type::None)
+            }
+            Expr::Loop { body, .. } => self.check_loop_body(program, *body),
+            Expr::For {
+                init,
+                cond,
+                step,
+                body,
+                ..
+            } => {
+                self.env.push_scope();
+                let result = self.check_for(program, [*init, *cond, *step], *body);
+                self.env.pop_scope();
+                result
+            }
+            Expr::ForIn {
+                var, iter, body, ..
+            } => {
+                let elem = match self.check_expr(program, *iter)? {
+                    Type::Array(elem) => *elem,
+                    Type::String => Type::Char,
+                    Type::Auto | Type::Never => Type::Auto,
+                    _ => return Err(self.error(ErrorKind::InvalidOperand, program.ast.span(*iter))),
+                };
+
+                let slot = self.binding_slot(program, *var)?;
+                self.env.push_scope();
+                self.env.define(slot, elem);
+                let result = self.check_loop_body(program, *body);
+                self.env.pop_scope();
+                result.map(|_| Type::None)
+            }
+            Expr::Break { value, span } => {
+                let ty = match value {
+                    Some(value) => self.check_expr(program, *value)?,
+                    None => Type::None,
+                };
+
+                if let Some(loop_ty) = self.loops.last() {
+                    if unlikely(!compatible(loop_ty, &ty)) {
+                        return Err(self.mismatch(loop_ty.clone(), ty, *span));
+                    }
+                    let joined = join(loop_ty.clone(), ty);
+                    *self.loops.last_mut().unwrap() = joined;
+                }
+
+                Ok(Type::Never)
+            }
+            Expr::Continue { .. } => Ok(Type::Never),
+            Expr::Func {
+                params,
+                ret,
+                ret_span,
+                body,
+                span,
+                ..
+            } => {
+                match program.slots.get(id) {
+                    Some(slot) => self.env.define(*slot, func_type(params, ret)),
+                    None => return Err(self.error(ErrorKind::UndefinedVariable, *span)),
+                }
+
+                let saved = self
+                    .env
+                    .enter_func(params.iter().map(|param| param.kind.clone()).collect());
+                self.returns.push((ret.clone(), *ret_span));
+                let loops = std::mem::take(&mut self.loops);
+
+                let result = self.check_expr(program, *body);
+
+                self.loops = loops;
+                self.returns.pop();
+                self.env.exit_func(saved);
+
+                let body_ty = result?;
+                if unlikely(!compatible(ret, &body_ty)) {
+                    let error = self.mismatch(ret.clone(), body_ty, program.ast.span(*body));
+                    return Err(match ret_span {
+                        Some(ret_span) => error.with_note(*ret_span, "expected because of this"),
+                        None => error,
+                    });
+                }
+
+                Ok(Type::None)
+            }
+            Expr::Call { callee, args, span } => {
+                let (params, ret) = match self.check_expr(program, *callee)? {
+                    Type::Func { params, ret } => (params, ret),
+                    Type::Auto | Type::Never => {
+                        for arg in args {
+                            self.check_expr(program, *arg)?;
+                        }
+                        return Ok(Type::Auto);
+                    }
+                    _ => return Err(self.error(ErrorKind::NotCallable, program.ast.span(*callee))),
+                };
+
+                if unlikely(params.len() != args.len()) {
+                    return Err(self.error(ErrorKind::ArgumentCountMismatch, *span));
+                }
+
+                for (param, arg) in params.iter().zip(args) {
+                    let arg_ty = self.check_expr(program, *arg)?;
+                    self.expect(param, &arg_ty, program.ast.span(*arg))?;
+                }
+
+                Ok(*ret)
+            }
+            Expr::Return { value, span } => {
+                let ty = match value {
+                    Some(value) => self.check_expr(program, *value)?,
+                    None => Type::None,
+                };
+
+                if let Some((ret, ret_span)) = self.returns.last()
+                    && unlikely(!compatible(ret, &ty))
+                {
+                    let value_span = value.map_or(*span, |value| program.ast.span(value));
+                    let error = self.mismatch(ret.clone(), ty, value_span);
+                    return Err(match ret_span {
+                        Some(ret_span) => error.with_note(*ret_span, "expected because of this"),
+                        None => error,
+                    });
+                }
+
+                Ok(Type::Never)
+            }
+            Expr::Let {
+                target,
+                kind,
+                kind_span,
+                init,
+                ..
+            } => {
+                let ty = match init {
+                    Some(init) => {
+                        let init_ty = self.check_expr(program, *init)?;
+                        if unlikely(!compatible(kind, &init_ty)) {
+                            let error =
+                                self.mismatch(kind.clone(), init_ty, program.ast.span(*init));
+                            return Err(match kind_span {
+                                Some(kind_span) => {
+                                    error.with_note(*kind_span, "expected because of this")
+                                }
+                                None => error,
+                            });
+                        }
+
+                        if *kind == Type::Auto {
+                            init_ty
+                        } else {
+                            kind.clone()
+                        }
+                    }
+                    None => kind.clone(),
+                };
+
+                let slot = self.binding_slot(program, *target)?;
+                self.env.define(slot, ty);
+                Ok(Type::None)
+            }
+            Expr::Const {
+                target,
+                kind,
+                kind_span,
+                value,
+                ..
+            } => {
+                let value_ty = self.check_expr(program, *value)?;
+                if unlikely(!compatible(kind, &value_ty)) {
+                    return Err(self
+                        .mismatch(kind.clone(), value_ty, program.ast.span(*value))
+                        .with_note(*kind_span, "expected because of this"));
+                }
+
+                let slot = self.binding_slot(program, *target)?;
+                self.env.define(slot, kind.clone());
+                Ok(Type::None)
+            }
+        }
+    }
+
+    fn check_sequence(
+        &mut self,
+        program: &Program,
+        exprs: &[NodeId],
+    ) -> Result<Type, CompilerError> {
+        // Functions are visible to the whole block, so calls may precede them.
+        for id in exprs {
+            if let Expr::Func { params, ret, .. } = &program.ast[*id]
+                && let Some(slot) = program.slots.get(*id)
+            {
+                self.env.define(*slot, func_type(params, ret));
+            }
+        }
+
+        let mut last = Type::None;
+        for id in exprs {
+            last = self.check_expr(program, *id)?;
+        }
+
+        Ok(last)
+    }
+
+    // A block without a tail still never finishes if one of its statements
+    // diverges, e.g. a `return`, an `if` that returns from both branches or a
+    // `loop` without `break`.
+    fn diverges(&self, exprs: &[NodeId]) -> bool {
+        exprs
+            .iter()
+            .any(|id| self.types.get(*id) == Some(&Type::Never))
+    }
+
+    fn check_loop_body(&mut self, program: &Program, body: NodeId) -> Result<Type, CompilerError> {
+        self.loops.push(Type::Never);
+        let result = self.check_expr(program, body);
+        let ty = self.loops.pop().unwrap();
+        result?;
+        Ok(ty)
+    }
+
+    fn check_for(
+        &mut self,
+        program: &Program,
+        [init, cond, step]: [Option<NodeId>; 3],
+        body: NodeId,
+    ) -> Result<Type, CompilerError> {
+        if let Some(init) = init {
+            self.check_expr(program, init)?;
+        }
+        if let Some(cond) = cond {
+            let cond_ty = self.check_expr(program, cond)?;
+            self.expect(&Type::Bool, &cond_ty, program.ast.span(cond))?;
+        }
+        if let Some(step) = step {
+            self.check_expr(program, step)?;
+        }
+
+        self.check_loop_body(program, body)?;
+        Ok(Type::None)
+    }
+
+    fn binary_type(
+        &self,
+        op: &BinOp,
+        left: Type,
+        right: Type,
+        span: Span,
+    ) -> Result<Type, CompilerError> {
+        let unknown = |ty: &Type| matches!(ty, Type::Auto | Type::Never);
+
+        let result = match op {
+            BinOp::Eq | BinOp::Neq => {
+                let comparable = !matches!(left, Type::Func { .. });
+                (comparable && compatible(&left, &right)).then_some(Type::Bool)
+            }
+            BinOp::Lt | BinOp::Gt | BinOp::Lte | BinOp::Gte => match (&left, &right) {
+                (l, r) if unknown(l) || unknown(r) => Some(Type::Bool),
+                (Type::Int, Type::Int) | (Type::Float, Type::Float) => Some(Type::Bool),
+                (Type::String, Type::String) | (Type::Char, Type::Char) => Some(Type::Bool),
+                _ => None,
+            },
+            BinOp::And | BinOp::Or => match (&left, &right) {
+                (Type::Bool | Type::Auto | Type::Never, Type::Bool | Type::Auto | Type::Never) => {
+                    Some(Type::Bool)
+                }
+                _ => None,
+            },
+            _ => match (&left, &right) {
+                (l, r) if unknown(l) && unknown(r) => Some(Type::Auto),
+                (l, r) if unknown(l) => Some(r.clone()),
+                (l, r) if unknown(r) => Some(l.clone()),
+                (Type::Int, Type::Int) => Some(Type::Int),
+                (Type::Float, Type::Float) => (!matches!(
+                    op,
+                    BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor | BinOp::Shl | BinOp::Shr
+                ))
+                .then_some(Type::Float),
+                (Type::String, Type::String) => (*op == BinOp::Add).then_some(Type::String),
+                (Type::Bool, Type::Bool) => {
+                    matches!(op, BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor).then_some(Type::Bool)
+                }
+                _ => None,
+            },
+        };
+
+        match result {
+            Some(ty) => Ok(ty),
+            None => Err(self.error(
+                ErrorKind::BinaryOperandTypes {
+                    op: op.clone(),
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+                span,
+            )),
+        }
+    }
+
+    fn binding_slot(&self, program: &Program, target: NodeId) -> Result<Slot, CompilerError> {
+        match program.slots.get(target) {
+            Some(slot) => Ok(*slot),
+            None => Err(self.error(ErrorKind::UndefinedVariable, program.ast.span(target))),
+        }
+    }
+
+    #[inline(always)]
+    fn expect(&self, expected: &Type, found: &Type, span: Span) -> Result<(), CompilerError> {
+        if likely(compatible(expected, found)) {
+            Ok(())
+        } else {
+            Err(self.mismatch(expected.clone(), found.clone(), span))
+        }
+    }
+
+    #[cold]
+    fn mismatch(&self, expected: Type, found: Type, span: Span) -> CompilerError {
+        self.error(
+            ErrorKind::TypeMismatch {
+                expected: Box::new(expected),
+                found: Box::new(found),
+            },
+            span,
+        )
+    }
+
+    #[cold]
+    fn error(&self, kind: ErrorKind, span: Span) -> CompilerError {
+        CompilerError::new(kind, span, self.input, self.filename.clone())
+    }
+}
+
+// `Auto` is a type that is not known yet and `Never` fits wherever a value is expected.
+fn compatible(expected: &Type, found: &Type) -> bool {
+    match (expected, found) {
+        (Type::Auto | Type::Never, _) | (_, Type::Auto | Type::Never) => true,
+        (Type::Array(a), Type::Array(b)) => compatible(a, b),
+        (
+            Type::Func {
+                params: a_params,
+                ret: a_ret,
+            },
+            Type::Func {
+                params: b_params,
+                ret: b_ret,
+            },
+        ) => {
+            a_params.len() == b_params.len()
+                && a_params.iter().zip(b_params).all(|(a, b)| compatible(a, b))
+                && compatible(a_ret, b_ret)
+        }
+        _ => expected == found,
+    }
+}
+
+// Functions have no meaningful text form, so they cannot be formatted.
+fn displayable(ty: &Type) -> bool {
+    match ty {
+        Type::Func { .. } => false,
+        Type::Array(elem) => displayable(elem),
+        _ => true,
+    }
+}
+
+// The more precise of two compatible types.
+fn join(a: Type, b: Type) -> Type {
+    match (a, b) {
+        (Type::Never, b) => b,
+        (a, Type::Never) => a,
+        (Type::Auto, b) => b,
+        (Type::Array(a), Type::Array(b)) => Type::Array(Box::new(join(*a, *b))),
+        (a, _) => a,
+    }
+}
+
+fn func_type(params: &[Param], ret: &Type) -> Type {
+    Type::Func {
+        params: params.iter().map(|param| param.kind.clone()).collect(),
+        ret: Box::new(ret.clone()),
+    }
+}
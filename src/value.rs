@@ -0,0 +1,82 @@
+use crate::Span;
+use crate::ast::{NodeId, Program};
+use crate::symbol::Symbol;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    String(String),
+    Char(char),
+    Bool(bool),
+    Array(Rc<Vec<Value>>),
+    Function(Rc<Function>),
+    None,
+}
+
+pub struct Function {
+    pub name: Symbol,
+    // Arguments fill the first `arity` slots of the call scope.
+    pub arity: usize,
+    // The body lives in the arena of the program that defined the function.
+    pub program: Rc<Program>,
+    pub body: NodeId,
+    pub span: Span,
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.program, &other.program) && self.body == other.body
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "str",
+            Value::Char(_) => "char",
+            Value::Bool(_) => "bool",
+            Value::Array(_) => "array",
+            Value::Function(_) => "func",
+            Value::None => "None",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Float(v) => write!(f, "{:?}", v),
+            Value::String(v) => write!(f, "{}", v),
+            Value::Char(v) => write!(f, "{}", v),
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Function(func) => write!(f, "<func {}>", func.name),
+            Value::None => write!(f, "None"),
+        }
+    }
+}
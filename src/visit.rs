@@ -0,0 +1,136 @@
+use crate::ast::{Ast, Expr, NodeId, Param};
+
+// Read-only traversal. Override the hooks a pass cares about and call the
+// matching `walk_*` function from them to keep descending.
+pub trait Visitor {
+    fn visit_expr(&mut self, ast: &Ast, id: NodeId) {
+        walk_expr(self, ast, id);
+    }
+
+    fn visit_param(&mut self, _param: &Param) {}
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, id: NodeId) {
+    if let Expr::Func { params, .. } = &ast[id] {
+        for param in params {
+            visitor.visit_param(param);
+        }
+    }
+
+    each_child(&ast[id], |child| visitor.visit_expr(ast, child));
+}
+
+// In-place traversal. Nodes may be edited freely, but the children that get
+// walked are the ones the node has once its hook hands it to `walk_expr_mut`.
+pub trait VisitorMut {
+    fn visit_expr_mut(&mut self, ast: &mut Ast, id: NodeId) {
+        walk_expr_mut(self, ast, id);
+    }
+
+    fn visit_param_mut(&mut self, _param: &mut Param) {}
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut Ast, id: NodeId) {
+    if let Expr::Func { params, .. } = &mut ast[id] {
+        for param in params {
+            visitor.visit_param_mut(param);
+        }
+    }
+
+    let mut index = 0;
+    while let Some(child) = nth_child(&ast[id], index) {
+        visitor.visit_expr_mut(ast, child);
+        index += 1;
+    }
+}
+
+// Rewriting traversal. Each node is replaced by the id its fold returns, which
+// may be a freshly allocated node. Runs before the resolver, since the side
+// tables of later passes are keyed by the old ids.
+pub trait Fold {
+    fn fold_expr(&mut self, ast: &mut Ast, id: NodeId) -> NodeId {
+        walk_fold(self, ast, id)
+    }
+}
+
+pub fn walk_fold<F: Fold + ?Sized>(folder: &mut F, ast: &mut Ast, id: NodeId) -> NodeId {
+    // The node is moved out while its children are folded, so its child ids
+    // can be rewritten without cloning it. Children never refer to it.
+    let span = ast.span(id);
+    let mut expr = std::mem::replace(&mut ast[id], Expr::Continue { span });
+    each_child_mut(&mut expr, |child| *child = folder.fold_expr(ast, *child));
+    ast[id] = expr;
+    id
+}
+
+// A child field holds one node, an optional one or a list of them.
+trait Children {
+    fn ids(&self) -> &[NodeId];
+    fn ids_mut(&mut self) -> &mut [NodeId];
+}
+
+impl Children for NodeId {
+    fn ids(&self) -> &[NodeId] {
+        std::slice::from_ref(self)
+    }
+
+    fn ids_mut(&mut self) -> &mut [NodeId] {
+        std::slice::from_mut(self)
+    }
+}
+
+impl Children for Option<NodeId> {
+    fn ids(&self) -> &[NodeId] {
+        self.as_slice()
+    }
+
+    fn ids_mut(&mut self) -> &mut [NodeId] {
+        self.as_mut_slice()
+    }
+}
+
+impl Children for Vec<NodeId> {
+    fn ids(&self) -> &[NodeId] {
+        self
+    }
+
+    fn ids_mut(&mut self) -> &mut [NodeId] {
+        self
+    }
+}
+
+macro_rules! child_accessors {
+    ($($variant:ident { $($field:ident),* }),* $(,)?) => {
+        // Calls `f` with every child of `expr`, in source order.
+        pub fn each_child(expr: &Expr, mut f: impl FnMut(NodeId)) {
+            match expr {
+                $(Expr::$variant { $($field,)* .. } => {
+                    $($field.ids().iter().copied().for_each(&mut f);)*
+                })*
+            }
+        }
+
+        pub fn each_child_mut(expr: &mut Expr, mut f: impl FnMut(&mut NodeId)) {
+            match expr {
+                $(Expr::$variant { $($field,)* .. } => {
+                    $($field.ids_mut().iter_mut().for_each(&mut f);)*
+                })*
+            }
+        }
+
+        // The child `each_child` would visit `index`th, found without walking
+        // the ones before it.
+        pub fn nth_child(expr: &Expr, index: usize) -> Option<NodeId> {
+            match expr {
+                $(Expr::$variant { $($field,)* .. } => {
+                    std::iter::empty::<&NodeId>()
+                        $(.chain($field.ids()))*
+                        .nth(index)
+                        .copied()
+                })*
+            }
+        }
+    };
+}
+
+expr_children!(child_accessors);
@@ -0,0 +1,41 @@
+// Setup shared by the test files, each of which uses only some of it.
+#![allow(dead_code)]
+
+use regula_project::ast::Program;
+use regula_project::{CompilerError, Globals, Lexer, Parser, Resolver, Span, Token};
+use std::rc::Rc;
+
+pub const FILENAME: &str = "<test>";
+
+pub fn input(source: &str) -> Rc<[u8]> {
+    Rc::from(source.as_bytes())
+}
+
+pub fn lexer(input: &Rc<[u8]>) -> Lexer<'_> {
+    Lexer::new(FILENAME.to_string(), input)
+}
+
+pub fn tokenize(source: &str) -> Result<Vec<Token>, Vec<CompilerError>> {
+    lexer(&input(source)).tokenize_all()
+}
+
+// Parses from a streaming lexer, like the driver does.
+pub fn parse(input: &Rc<[u8]>) -> Result<Program, Vec<CompilerError>> {
+    Parser::from_lexer(lexer(input), input, FILENAME.to_string()).parse()
+}
+
+// Parses and resolves, stopping at the first error.
+pub fn resolve(input: &Rc<[u8]>, globals: &mut Globals) -> Result<Program, CompilerError> {
+    let mut program = parse(input).map_err(|mut errors| errors.remove(0))?;
+    Resolver::new(globals, input, FILENAME.to_string()).resolve(&mut program)?;
+    Ok(program)
+}
+
+pub fn text(source: &str, span: Span) -> String {
+    source[span.start..span.end].to_string()
+}
+
+// The kind of an error and the text it points at.
+pub fn describe(source: &str, e: &CompilerError) -> (String, String) {
+    (e.kind.to_string(), text(source, e.span))
+}
@@ -0,0 +1,342 @@
+mod common;
+
+use regula_project::error::MAX_ERRORS;
+use regula_project::{ErrorKind, TokenType};
+
+fn kinds(source: &str) -> Vec<TokenType> {
+    let mut tokens: Vec<_> = common::tokenize(source)
+        .unwrap()
+        .into_iter()
+        .map(|token| token.token_type)
+        .collect();
+    assert_eq!(tokens.pop(), Some(TokenType::Eof));
+    tokens
+}
+
+#[test]
+fn arrow() {
+    assert_eq!(kinds("->"), [TokenType::Arrow]);
+    assert_eq!(kinds("- >"), [TokenType::Minus, TokenType::Gt]);
+    assert_eq!(kinds("-="), [TokenType::MinusAssign]);
+}
+
+#[test]
+fn fat_arrow() {
+    assert_eq!(kinds("=>"), [TokenType::FatArrow]);
+    assert_eq!(kinds("==>"), [TokenType::Eq, TokenType::Gt]);
+    assert_eq!(
+        kinds("a, b => a - b"),
+        [
+            TokenType::Identifier,
+            TokenType::Comma,
+            TokenType::Identifier,
+            TokenType::FatArrow,
+            TokenType::Identifier,
+            TokenType::Minus,
+            TokenType::Identifier,
+        ]
+    );
+}
+
+#[test]
+fn double_colon() {
+    assert_eq!(kinds("::"), [TokenType::DoubleColon]);
+    assert_eq!(kinds(":::"), [TokenType::DoubleColon, TokenType::Colon]);
+    assert_eq!(
+        kinds("io::println!(i)"),
+        [
+            TokenType::Identifier,
+            TokenType::DoubleColon,
+            TokenType::Identifier,
+            TokenType::Not,
+            TokenType::LParen,
+            TokenType::Identifier,
+            TokenType::RParen,
+        ]
+    );
+}
+
+#[test]
+fn ranges() {
+    assert_eq!(kinds(".."), [TokenType::DotDot]);
+    assert_eq!(kinds("..="), [TokenType::DotDotEq]);
+    assert_eq!(kinds("..."), [TokenType::DotDot, TokenType::Dot]);
+    assert_eq!(
+        kinds("a.b"),
+        [TokenType::Identifier, TokenType::Dot, TokenType::Identifier]
+    );
+}
+
+#[test]
+fn ranges_after_numbers() {
+    assert_eq!(
+        kinds("1..10"),
+        [TokenType::Int, TokenType::DotDot, TokenType::Int]
+    );
+    assert_eq!(
+        kinds("0..=9"),
+        [TokenType::Int, TokenType::DotDotEq, TokenType::Int]
+    );
+    assert_eq!(
+        kinds("1.5..2.5"),
+        [TokenType::Float, TokenType::DotDot, TokenType::Float]
+    );
+    assert_eq!(kinds("1."), [TokenType::Float]);
+}
+
+#[test]
+fn question() {
+    assert_eq!(kinds("?"), [TokenType::Question]);
+    assert_eq!(
+        kinds("f()?;"),
+        [
+            TokenType::Identifier,
+            TokenType::LParen,
+            TokenType::RParen,
+            TokenType::Question,
+            TokenType::Semicolon,
+        ]
+    );
+}
+
+#[test]
+fn long_identifiers() {
+    assert_eq!(
+        kinds("let running_total_value_abc = 1;"),
+        [
+            TokenType::Let,
+            TokenType::Identifier,
+            TokenType::Assign,
+            TokenType::Int,
+            TokenType::Semicolon,
+        ]
+    );
+    assert_eq!(kinds(&"x".repeat(1000)), [TokenType::Identifier]);
+    assert_eq!(kinds("continue_"), [TokenType::Identifier]);
+}
+
+#[test]
+fn three_byte_symbols() {
+    assert_eq!(kinds("<<="), [TokenType::BitwiseShlAssign]);
+    assert_eq!(kinds(">>="), [TokenType::BitwiseShrAssign]);
+    assert_eq!(kinds("**="), [TokenType::PowerAssign]);
+    assert_eq!(kinds("<<"), [TokenType::BitwiseShl]);
+    assert_eq!(kinds("<="), [TokenType::LtE]);
+}
+
+#[test]
+fn longest_match_wins() {
+    assert_eq!(kinds("<<<="), [TokenType::BitwiseShl, TokenType::LtE]);
+    assert_eq!(kinds("..=="), [TokenType::DotDotEq, TokenType::Assign]);
+    assert_eq!(kinds("=>="), [TokenType::FatArrow, TokenType::Assign]);
+}
+
+const OPERATORS: &[(&str, TokenType)] = &[
+    ("+", TokenType::Plus),
+    ("-", TokenType::Minus),
+    ("*", TokenType::Star),
+    ("/", TokenType::Slash),
+    ("%", TokenType::Mod),
+    ("**", TokenType::Power),
+    ("=", TokenType::Assign),
+    ("==", TokenType::Eq),
+    ("!=", TokenType::Ne),
+    ("<", TokenType::Lt),
+    (">", TokenType::Gt),
+    ("<=", TokenType::LtE),
+    (">=", TokenType::GtE),
+    ("!", TokenType::Not),
+    ("&&", TokenType::And),
+    ("||", TokenType::Or),
+    ("&", TokenType::Ampersand),
+    ("|", TokenType::Pipe),
+    ("^", TokenType::BitwiseXor),
+    ("~", TokenType::Tilde),
+    ("<<", TokenType::BitwiseShl),
+    (">>", TokenType::BitwiseShr),
+    ("+=", TokenType::PlusAssign),
+    ("-=", TokenType::MinusAssign),
+    ("*=", TokenType::StarAssign),
+    ("/=", TokenType::SlashAssign),
+    ("%=", TokenType::ModAssign),
+    ("**=", TokenType::PowerAssign),
+    ("&=", TokenType::AmpersandAssign),
+    ("|=", TokenType::PipeAssign),
+    ("^=", TokenType::BitwiseXorAssign),
+    ("<<=", TokenType::BitwiseShlAssign),
+    (">>=", TokenType::BitwiseShrAssign),
+    ("->", TokenType::Arrow),
+    ("=>", TokenType::FatArrow),
+    (":", TokenType::Colon),
+    ("::", TokenType::DoubleColon),
+    (".", TokenType::Dot),
+    ("..", TokenType::DotDot),
+    ("..=", TokenType::DotDotEq),
+    ("?", TokenType::Question),
+    (",", TokenType::Comma),
+    (";", TokenType::Semicolon),
+    ("(", TokenType::LParen),
+    (")", TokenType::RParen),
+    ("[", TokenType::LBracket),
+    ("]", TokenType::RBracket),
+    ("{", TokenType::LBrace),
+    ("}", TokenType::RBrace),
+];
+
+#[test]
+fn operators_at_every_position() {
+    // Operands that cannot merge with any operator, including the empty one
+    // so that operators also end up at the very start and end of the input.
+    // A number is followed by a space since `1.` is a float.
+    let operands: &[(&str, &[TokenType])] = &[
+        ("", &[]),
+        ("a", &[TokenType::Identifier]),
+        ("1 ", &[TokenType::Int]),
+        (" x ", &[TokenType::Identifier]),
+        ("\n", &[]),
+    ];
+
+    for (op, kind) in OPERATORS {
+        for (before, before_kinds) in operands {
+            for (after, after_kinds) in operands {
+                let source = format!("{before}{op}{after}");
+                let expected: Vec<_> = before_kinds
+                    .iter()
+                    .chain([kind])
+                    .chain(after_kinds.iter())
+                    .copied()
+                    .collect();
+                assert_eq!(kinds(&source), expected, "{source:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn random_operator_sequences() {
+    // xorshift, so that failures are reproducible.
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as usize
+    };
+
+    for _ in 0..2000 {
+        let mut source = String::new();
+        let mut expected = Vec::new();
+        for _ in 0..next() % 12 {
+            let (op, kind) = OPERATORS[next() % OPERATORS.len()];
+            // Adjacent operators are kept apart so that they cannot merge.
+            if !source.is_empty() {
+                source.push_str([" ", "\t", "\n", " /* */ "][next() % 4]);
+            }
+            source.push_str(op);
+            expected.push(kind);
+            if next() % 3 == 0 {
+                source.push_str(" n");
+                expected.push(TokenType::Identifier);
+            }
+        }
+        assert_eq!(kinds(&source), expected, "{source:?}");
+    }
+}
+
+fn errors(source: &str) -> Vec<(String, String)> {
+    common::tokenize(source)
+        .unwrap_err()
+        .iter()
+        .map(|e| common::describe(source, e))
+        .collect()
+}
+
+#[test]
+fn reports_every_error() {
+    assert_eq!(
+        errors("let a = #; let b = \"\\q\"; let c = @;"),
+        [
+            ("UnexpectedCharacter".to_string(), "#".to_string()),
+            ("InvalidEscape".to_string(), "\\q".to_string()),
+            ("UnexpectedCharacter".to_string(), "@".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn caps_collected_errors() {
+    let errors = errors(&"#".repeat(250));
+    assert_eq!(errors.len(), MAX_ERRORS + 1);
+    assert_eq!(
+        errors[MAX_ERRORS],
+        ("TooManyErrors".to_string(), "#".to_string())
+    );
+
+    let last = common::tokenize(&"#".repeat(250))
+        .unwrap_err()
+        .pop()
+        .unwrap();
+    assert!(matches!(
+        last.kind,
+        ErrorKind::TooManyErrors { omitted: 150 }
+    ));
+}
+
+#[test]
+fn invalid_escapes() {
+    let invalid = |source: &str, escape: &str| {
+        assert_eq!(
+            errors(source),
+            [(String::from("InvalidEscape"), escape.to_string())]
+        );
+    };
+    invalid(r#""a\qb""#, r"\q");
+    invalid(r#""\x80""#, r"\x80");
+    invalid(r#""\x4""#, r"\x4");
+    invalid(r#""\u{}""#, r"\u{");
+    invalid(r#""\u{1234567}""#, r"\u{123456");
+    invalid(r#""\u{D800}""#, r"\u{D800}");
+    invalid(r#""\u41""#, r"\u");
+    invalid(r"'\q'", r"\q");
+}
+
+fn spans(source: &str) -> Vec<&str> {
+    let mut tokens = common::tokenize(source).unwrap();
+    assert_eq!(
+        tokens.pop().map(|token| token.token_type),
+        Some(TokenType::Eof)
+    );
+    tokens
+        .iter()
+        .map(|token| &source[token.span.start..token.span.end])
+        .collect()
+}
+
+#[test]
+fn comments() {
+    assert_eq!(spans("a /* b */ c"), ["a", "c"]);
+    assert_eq!(spans("a /* /* b */ c */ d"), ["a", "d"]);
+    assert_eq!(spans("/* /* */ */x/**/y"), ["x", "y"]);
+    assert_eq!(spans("a // b /* c\nd"), ["a", "d"]);
+    assert_eq!(spans("a /* // */ b"), ["a", "b"]);
+    assert_eq!(spans("a /* é */ bc"), ["a", "bc"]);
+    assert_eq!(spans("a /**/"), ["a"]);
+}
+
+#[test]
+fn unclosed_comments() {
+    let unclosed = [(String::from("UnclosedComment"), String::from("/*"))];
+    assert_eq!(errors("a /* b"), unclosed);
+    assert_eq!(errors("a /* /* b */"), unclosed);
+    assert_eq!(spans("a /* b */ */"), ["a", "*", "/"]);
+}
+
+#[test]
+fn unclosed_format_strings() {
+    let unclosed = |text: &str| [(String::from("UnclosedString"), text.to_string())];
+    assert_eq!(errors("let s = f\"ab"), unclosed("f\"ab"));
+    assert_eq!(errors("let s = f\"{1}"), unclosed("f\"{1}"));
+    assert_eq!(errors("let s = f\"{"), unclosed("f\"{"));
+    assert_eq!(errors("let s = f\"{1 + (2"), unclosed("f\"{1 + (2"));
+    assert_eq!(errors("let s = f\"a{f\"{ "), unclosed("f\"{ "));
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::FILENAME;
+use regula_project::{Lexer, TokenType};
+use std::fs;
+
+fn round_trip(source: &str) -> String {
+    let input = common::input(source);
+    let mut lexer = Lexer::lossless(FILENAME.to_string(), &input);
+    let mut output = Vec::with_capacity(input.len());
+
+    loop {
+        let token = lexer.next_token().unwrap();
+        for span in [token.leading, token.span, token.trailing] {
+            output.extend_from_slice(&input[span.start..span.end]);
+        }
+        if token.token_type == TokenType::Eof {
+            break;
+        }
+    }
+
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn examples_round_trip() {
+    for entry in fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        let source = fs::read_to_string(&path).unwrap();
+        assert_eq!(round_trip(&source), source, "{}", path.display());
+    }
+}
+
+#[test]
+fn trivia_round_trips() {
+    let source =
+        "  //! about\n/// doc\nlet x = 1; // trailing\n\n/* a /* b */ c */ f\"{ x } \" \t\n";
+    assert_eq!(round_trip(source), source);
+}
+
+#[test]
+fn trailing_trivia_stops_at_newline() {
+    let input = common::input("a // c\n  b");
+    let mut lexer = Lexer::lossless(FILENAME.to_string(), &input);
+
+    let a = lexer.next_token().unwrap();
+    assert_eq!(&input[a.trailing.start..a.trailing.end], b" // c");
+
+    let b = lexer.next_token().unwrap();
+    assert_eq!(&input[b.leading.start..b.leading.end], b"\n  ");
+}
@@ -0,0 +1,242 @@
+mod common;
+
+use common::FILENAME;
+use regula_project::ast::{Ast, Expr, Literal, NodeId};
+use regula_project::{CompilerError, Parser};
+
+// Renders the operator structure of an expression with explicit parentheses.
+fn shape(ast: &Ast, id: NodeId) -> String {
+    match &ast[id] {
+        Expr::Binary {
+            op, left, right, ..
+        } => format!("({} {} {})", shape(ast, *left), op, shape(ast, *right)),
+        Expr::Unary { op, expr, .. } => format!("({}{})", op, shape(ast, *expr)),
+        Expr::Literal {
+            lit: Literal::Int(v),
+            ..
+        } => v.to_string(),
+        Expr::Literal {
+            lit: Literal::Float(v),
+            ..
+        } => v.to_string(),
+        Expr::Literal {
+            lit: Literal::String(v),
+            ..
+        } => format!("{v:?}"),
+        Expr::Literal {
+            lit: Literal::Char(v),
+            ..
+        } => format!("{v:?}"),
+        Expr::Ident { name, .. } => name.to_string(),
+        expr => panic!("unexpected expression {expr:?}"),
+    }
+}
+
+fn parse_expr(source: &str) -> String {
+    let program = common::parse(&common::input(&format!("{source};"))).unwrap();
+    match &program.ast[program.body] {
+        Expr::Block { exprs, .. } => shape(&program.ast, exprs[0]),
+        _ => shape(&program.ast, program.body),
+    }
+}
+
+#[test]
+fn bitwise_precedence() {
+    assert_eq!(parse_expr("a | b ^ c & d"), "(a | (b ^ (c & d)))");
+    assert_eq!(parse_expr("a & b << 1"), "(a & (b << 1))");
+    assert_eq!(parse_expr("a << 1 + 2"), "(a << (1 + 2))");
+    assert_eq!(parse_expr("a >> 1 >> 2"), "((a >> 1) >> 2)");
+    assert_eq!(parse_expr("a & 1 == 0"), "((a & 1) == 0)");
+    assert_eq!(parse_expr("a | b < c"), "((a | b) < c)");
+    assert_eq!(parse_expr("a | b && c"), "((a | b) && c)");
+}
+
+#[test]
+fn bitwise_not() {
+    assert_eq!(parse_expr("~a & b"), "((~a) & b)");
+    assert_eq!(parse_expr("~~a"), "(~(~a))");
+    assert_eq!(parse_expr("-~a"), "(-(~a))");
+}
+
+fn parse_errors(source: &str) -> Vec<(String, String)> {
+    common::parse(&common::input(source))
+        .unwrap_err()
+        .iter()
+        .map(|e| common::describe(source, e))
+        .collect()
+}
+
+#[test]
+fn lexer_and_parse_errors_together() {
+    assert_eq!(
+        parse_errors("let a = \"\\q\"; let b = 1 +; let c = #;"),
+        [
+            ("InvalidEscape".to_string(), "\\q".to_string()),
+            ("InvalidSyntax".to_string(), ";".to_string()),
+            ("UnexpectedCharacter".to_string(), "#".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn recovers_after_statement() {
+    assert_eq!(
+        parse_errors("func f() { let x = 1 +; } let y = ; let z = 2; func g() { 1 + }"),
+        [
+            ("InvalidSyntax".to_string(), ";".to_string()),
+            ("InvalidSyntax".to_string(), ";".to_string()),
+            ("InvalidSyntax".to_string(), "}".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn recovers_after_block_statement() {
+    assert_eq!(
+        parse_errors("let a = { 1 + }; let b = 2; let c = [1 +];"),
+        [
+            ("InvalidSyntax".to_string(), "}".to_string()),
+            ("InvalidSyntax".to_string(), "]".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn statement_starting_with_a_lexer_error() {
+    assert_eq!(
+        parse_errors("let a = 1; 1i7; let b = 2 +;"),
+        [
+            ("InvalidNumber".to_string(), "1i7".to_string()),
+            ("InvalidSyntax".to_string(), ";".to_string()),
+        ]
+    );
+    assert_eq!(parse_errors("let a = 1; 1i7;").len(), 1);
+}
+
+#[test]
+fn negative_literals() {
+    assert_eq!(parse_expr("-128i8"), "-128");
+    assert_eq!(parse_expr("-32768i16"), "-32768");
+    assert_eq!(parse_expr("-0x8000_0000i32"), "-2147483648");
+    assert_eq!(parse_expr("-9223372036854775808"), "-9223372036854775808");
+    assert_eq!(parse_expr("-0u8"), "0");
+    assert_eq!(parse_expr("-5 ** 2"), "(-5 ** 2)");
+    assert_eq!(parse_expr("1 - 2"), "(1 - 2)");
+    assert_eq!(parse_expr("-a"), "(-a)");
+}
+
+#[test]
+fn literal_overflow() {
+    for (source, literal) in [
+        ("128i8;", "128i8"),
+        ("-129i8;", "-129i8"),
+        ("256u8;", "256u8"),
+        ("-1u8;", "-1u8"),
+        ("4294967296u32;", "4294967296u32"),
+        ("9223372036854775808;", "9223372036854775808"),
+        ("-9223372036854775809;", "-9223372036854775809"),
+        ("0x1_0000_0000_0000_0000;", "0x1_0000_0000_0000_0000"),
+    ] {
+        assert_eq!(
+            parse_errors(source),
+            [("IntegerOverflow".to_string(), literal.to_string())]
+        );
+    }
+
+    for source in ["1e309;", "1e39f32;"] {
+        assert_eq!(
+            parse_errors(source),
+            [(
+                "FloatOverflow".to_string(),
+                source.trim_end_matches(';').to_string()
+            )]
+        );
+    }
+}
+
+#[test]
+fn literal_suffixes() {
+    assert_eq!(parse_expr("255u8"), "255");
+    assert_eq!(parse_expr("1u64"), "1");
+    assert_eq!(parse_expr("1.5f32"), "1.5");
+    for source in ["1i7;", "1u;", "1.5i8;", "0b102;", "0x;", "1e;"] {
+        assert_eq!(
+            parse_errors(source),
+            [(
+                "InvalidNumber".to_string(),
+                source.trim_end_matches(';').to_string()
+            )]
+        );
+    }
+}
+
+#[test]
+fn digit_separators() {
+    assert_eq!(parse_expr("1_000_000"), "1000000");
+    assert_eq!(parse_expr("0x_ff"), "255");
+    assert_eq!(parse_expr("0b1010_1010"), "170");
+    assert_eq!(parse_expr("0o7_7"), "63");
+    assert_eq!(parse_expr("1__0_"), "10");
+    assert_eq!(parse_expr("1_000.5"), "1000.5");
+    assert_eq!(parse_expr("-1_000i16"), "-1000");
+}
+
+#[test]
+fn escapes() {
+    assert_eq!(parse_expr(r#""\n\t\r\0""#), r#""\n\t\r\0""#);
+    assert_eq!(parse_expr(r#""\\ \" \'""#), r#""\\ \" '""#);
+    assert_eq!(parse_expr(r#""\x41\x7f""#), r#""A\u{7f}""#);
+    assert_eq!(parse_expr(r#""\u{e9}\u{1F600}""#), r#""é😀""#);
+    assert_eq!(parse_expr(r"'\n'"), r"'\n'");
+    assert_eq!(parse_expr(r"'\''"), r"'\''");
+    assert_eq!(parse_expr(r"'\x41'"), "'A'");
+    assert_eq!(parse_expr(r"'\u{1F600}'"), "'😀'");
+}
+
+// Parses `source` from a token slice and from a streaming lexer.
+fn both_modes(source: &str) -> (String, String) {
+    let input = common::input(source);
+    let render = |result: Result<_, Vec<CompilerError>>| match result {
+        Ok(program) => format!("{program:?}"),
+        Err(errors) => format!("{:?}", errors.iter().map(|e| e.span).collect::<Vec<_>>()),
+    };
+
+    let slice = match common::tokenize(source) {
+        Ok(tokens) => render(Parser::new(&tokens, &input, FILENAME.to_string()).parse()),
+        Err(errors) => render(Err(errors)),
+    };
+    let stream = render(common::parse(&input));
+    (slice, stream)
+}
+
+#[test]
+fn slice_and_stream_agree() {
+    let sources = [
+        "func main() -> int { let mut a = [1, 2, 3]; a[0] += -1; a[0] }",
+        "func f(a: int, mut b: float) -> float { if a > 0 { b } else { -b } }",
+        "let s = f\"{1 + 2} and {\"x\"}\"; for c in s { if c == 'a' { break; } }",
+        "for let mut i = 0; i < 10; i += 1 { while true { continue; } }",
+        "/* doc */ const N: int = 1_000i64 << 2 | ~3; loop { break N; }",
+        "let a = 1 +; let b = ;",
+        "let a = -1(2);",
+        "let a = -1[0];",
+        "let a = -1.abs; let b = -2;",
+    ];
+    for source in sources {
+        let (slice, stream) = both_modes(source);
+        assert_eq!(slice, stream, "{source}");
+    }
+}
+
+#[test]
+fn declarations_are_not_tails() {
+    let missing = |text: &str| [("MissingSemicolon".to_string(), text.to_string())];
+    assert_eq!(parse_errors("let x = { let y = 1 };"), missing("}"));
+    assert_eq!(parse_errors("func f() { const C: int = 1 }"), missing("}"));
+    assert_eq!(parse_errors("if true { let y = 1 }"), missing("}"));
+
+    let source = "let x = { let y = 1; }; if true { let y = 1; }";
+    let (slice, stream) = both_modes(source);
+    assert!(slice.starts_with("Program"), "{slice}");
+    assert_eq!(slice, stream);
+}
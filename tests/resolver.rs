@@ -0,0 +1,130 @@
+mod common;
+
+use regula_project::ast::{Ast, Expr, NodeId, Program, Slot};
+use regula_project::visit::walk_expr;
+use regula_project::{CompilerError, Globals, Visitor};
+
+fn resolve(source: &str) -> Result<Program, CompilerError> {
+    common::resolve(&common::input(source), &mut Globals::new())
+}
+
+fn error(source: &str) -> (String, String) {
+    common::describe(source, &resolve(source).unwrap_err())
+}
+
+// Every identifier in source order, with the slot it was resolved to.
+struct Slots<'a>(&'a Program, Vec<(String, Option<Slot>)>);
+
+impl Visitor for Slots<'_> {
+    fn visit_expr(&mut self, ast: &Ast, id: NodeId) {
+        if let Expr::Ident { name, .. } = &ast[id] {
+            self.1
+                .push((name.to_string(), self.0.slots.get(id).cloned()));
+        }
+        walk_expr(self, ast, id);
+    }
+}
+
+fn slots(source: &str) -> Vec<(String, Option<Slot>)> {
+    let program = resolve(source).unwrap();
+    let mut slots = Slots(&program, Vec::new());
+    slots.visit_expr(&program.ast, program.body);
+    slots.1
+}
+
+fn local(name: &str, depth: usize, index: usize) -> (String, Option<Slot>) {
+    (name.to_string(), Some(Slot::Local { depth, index }))
+}
+
+fn global(name: &str, index: usize) -> (String, Option<Slot>) {
+    (name.to_string(), Some(Slot::Global(index)))
+}
+
+// Parameters live one scope above the body block.
+#[test]
+fn locals() {
+    assert_eq!(
+        slots("func f(a: int, b: int) -> int { let c = a; { let d = b; c + d } }"),
+        [
+            local("c", 0, 0),
+            local("a", 1, 0),
+            local("d", 0, 0),
+            local("b", 2, 1),
+            local("c", 1, 0),
+            local("d", 0, 0),
+        ]
+    );
+}
+
+// Functions are declared before the statements around them run.
+#[test]
+fn globals() {
+    assert_eq!(
+        slots("let x = 1; func f() -> int { x } let y = f();"),
+        [
+            global("x", 1),
+            global("x", 1),
+            global("y", 2),
+            global("f", 0),
+        ]
+    );
+}
+
+#[test]
+fn use_before_declaration() {
+    assert_eq!(
+        error("let a = b; let b = 1;"),
+        (String::from("UseBeforeDeclaration"), String::from("b"))
+    );
+    assert_eq!(
+        error("func f() -> int { let a = a; a }"),
+        (String::from("UseBeforeDeclaration"), String::from("a"))
+    );
+    assert_eq!(
+        error("func f() { let x = 1; func g() -> int { x } }"),
+        (String::from("CapturedLocal"), String::from("x"))
+    );
+    assert_eq!(
+        error("func f() -> int { y }"),
+        (String::from("UndefinedVariable"), String::from("y"))
+    );
+}
+
+// The span the error points at and the span and message of its note.
+type Diagnostic = ((usize, usize), Option<((usize, usize), &'static str)>);
+
+fn immutable(source: &str) -> Diagnostic {
+    let e = resolve(source).unwrap_err();
+    assert_eq!(e.kind.to_string(), "ImmutableAssignment");
+    (
+        (e.span.start, e.span.end),
+        e.note.map(|(span, note)| ((span.start, span.end), note)),
+    )
+}
+
+#[test]
+fn immutable_assignment() {
+    assert_eq!(
+        immutable("let a = 1; a = 2;"),
+        ((11, 16), Some(((4, 5), "declared here without `mut`")))
+    );
+    assert_eq!(
+        immutable("let a = [1];\na[0] = 2;"),
+        ((13, 21), Some(((4, 5), "declared here without `mut`")))
+    );
+    assert_eq!(
+        immutable("func f(p: int) { p += 1; }"),
+        (
+            (17, 23),
+            Some(((7, 8), "parameter declared here without `mut`"))
+        )
+    );
+    assert_eq!(
+        immutable("const C: int = 1; func f() { C = 2; }"),
+        ((29, 34), Some(((6, 7), "constant declared here")))
+    );
+    assert_eq!(
+        immutable("func g() {} func f() { g = 1; }"),
+        ((23, 28), Some(((5, 6), "function declared here")))
+    );
+}
@@ -0,0 +1,253 @@
+mod common;
+
+use common::FILENAME;
+use regula_project::{Environment, Globals, Interpreter, TypeChecker, TypeEnv, Value};
+use std::rc::Rc;
+
+// Runs `source` like a script file, calling `main` if it defines one, and
+// returns the kind of the first error otherwise.
+fn run(source: &str) -> Result<Value, String> {
+    let input = common::input(source);
+    let mut globals = Globals::new();
+    let program = common::resolve(&input, &mut globals).map_err(|e| e.kind.to_string())?;
+    let mut types = TypeEnv::new();
+    TypeChecker::new(&mut types, &input, FILENAME.to_string())
+        .check(&program)
+        .map_err(|e| e.kind.to_string())?;
+
+    let mut env = Environment::new();
+    let mut interpreter = Interpreter::new(&mut env, &input, FILENAME.to_string());
+    let mut value = interpreter
+        .run(&Rc::new(program))
+        .map_err(|e| e.kind.to_string())?;
+    if let Some(main) = globals.lookup("main") {
+        value = interpreter
+            .call_global(main)
+            .unwrap()
+            .map_err(|e| e.kind.to_string())?;
+    }
+    Ok(value)
+}
+
+#[test]
+fn nested_functions_see_each_other() {
+    assert_eq!(
+        run("func outer() -> int {
+                func r(n: int) -> int { if n == 0 { 0 } else { r(n - 1) } }
+                r(3)
+            }
+            func main() -> int { outer() }"),
+        Ok(Value::Int(0))
+    );
+    assert_eq!(
+        run("func main() -> int {
+                func even(n: int) -> bool { if n == 0 { true } else { odd(n - 1) } }
+                func odd(n: int) -> bool { if n == 0 { false } else { even(n - 1) } }
+                func sum(n: int) -> int { if n == 0 { 0 } else { n + sum(n - 1) } }
+                if even(10) { sum(4) } else { 0 }
+            }"),
+        Ok(Value::Int(10))
+    );
+}
+
+#[test]
+fn nested_functions_capture_nothing() {
+    // A local of the enclosing body is an error, even with a global of the
+    // same name to fall back on.
+    assert_eq!(
+        run("let x = 5;
+            func main() -> int {
+                let x = 1;
+                func inner() -> int { x }
+                inner()
+            }"),
+        Err(String::from("CapturedLocal"))
+    );
+    assert_eq!(
+        run("func main() -> int {
+                let y = 1;
+                func inner() -> int { y }
+                inner()
+            }"),
+        Err(String::from("CapturedLocal"))
+    );
+    assert_eq!(
+        run("func f(p: int) -> int {
+                func inner() -> int { p }
+                inner()
+            }"),
+        Err(String::from("CapturedLocal"))
+    );
+    assert_eq!(
+        run("func main() -> int {
+                func a() -> int { 1 }
+                func b() -> int { a = b; 1 }
+                b()
+            }"),
+        Err(String::from("ImmutableAssignment"))
+    );
+}
+
+#[test]
+fn functions_are_statements() {
+    assert_eq!(
+        run("let f = func g() -> int { 2 }; f();"),
+        Err(String::from("InvalidSyntax"))
+    );
+    assert_eq!(
+        run("func main() -> int { 1 + func g() -> int { 2 } }"),
+        Err(String::from("InvalidSyntax"))
+    );
+    assert_eq!(
+        run("func main() -> int {
+                /// Doubles `a`.
+                func g(a: int) -> int { a * 2 }
+                g(2)
+            }"),
+        Ok(Value::Int(4))
+    );
+}
+
+#[test]
+fn uninitialized_bindings_need_mut() {
+    assert_eq!(run("let x; x = 1;"), Err(String::from("MissingAssignment")));
+    assert_eq!(
+        run("let x: int; x = 1;"),
+        Err(String::from("MissingAssignment"))
+    );
+    // The first assignment fixes the type of a `let mut` without one.
+    assert_eq!(
+        run("let mut x; x = 1; let y: int = x; func main() -> int { y + 1 }"),
+        Ok(Value::Int(2))
+    );
+    assert_eq!(
+        run("let mut x; x = 1; x = \"a\";"),
+        Err(String::from("TypeMismatch"))
+    );
+}
+
+#[test]
+fn strings_iterate_as_chars() {
+    assert_eq!(
+        run("func main() -> int {
+                let mut n = 0;
+                for c in \"abca\" {
+                    let d: char = c;
+                    if d == 'a' { n += 1; }
+                }
+                n
+            }"),
+        Ok(Value::Int(2))
+    );
+    assert_eq!(
+        run("for c in \"ab\" { let s: str = c; }"),
+        Err(String::from("TypeMismatch"))
+    );
+}
+
+#[test]
+fn diverging_statements() {
+    assert_eq!(
+        run(
+            "func f(c: bool) -> int { if c { return 1; } else { return 2; }; }
+            func g() -> int { loop { return 3; }; }
+            func h() -> int { { return 4; }; }
+            func main() -> int { f(true) + f(false) + g() + h() }"
+        ),
+        Ok(Value::Int(10))
+    );
+    assert_eq!(
+        run("func f(c: bool) -> int { if c { return 1; }; }"),
+        Err(String::from("TypeMismatch"))
+    );
+    assert_eq!(
+        run("func f(c: bool) -> int { loop { if c { break; } return 1; }; }"),
+        Err(String::from("TypeMismatch"))
+    );
+}
+
+#[test]
+fn evaluates_expressions() {
+    assert_eq!(run("1 + 2 * 3;"), Ok(Value::Int(7)));
+    assert_eq!(
+        run("func main() -> int { 1 + 2 * 3 - 8 / 2 % 3 }"),
+        Ok(Value::Int(6))
+    );
+    assert_eq!(run("func main() -> int { 2 ** 10 }"), Ok(Value::Int(1024)));
+    assert_eq!(
+        run("func main() -> float { 1.5 * 2.0 + 0.5 }"),
+        Ok(Value::Float(3.5))
+    );
+    assert_eq!(
+        run("func main() -> bool { !(1 < 2) || 3 >= 3 && 1 != 2 }"),
+        Ok(Value::Bool(true))
+    );
+    assert_eq!(
+        run("func main() -> str { \"ab\" + \"cd\" }"),
+        Ok(Value::String(String::from("abcd")))
+    );
+    assert_eq!(run("func main() -> int { -(3 - 5) }"), Ok(Value::Int(2)));
+}
+
+#[test]
+fn bindings_and_blocks() {
+    assert_eq!(
+        run("const LIMIT: int = 10;
+            let mut total = 0;
+            func main() -> int {
+                let x = 1;
+                let x = x + 1;
+                {
+                    let y = x * LIMIT;
+                    total = total + y;
+                }
+                total += x;
+                total
+            }"),
+        Ok(Value::Int(22))
+    );
+    assert_eq!(
+        run("func main() -> int { let a = { let b = 2; b * b }; a }"),
+        Ok(Value::Int(4))
+    );
+}
+
+#[test]
+fn arrays_and_loops() {
+    assert_eq!(
+        run("func main() -> int {
+                let mut a = [1, 2, 3];
+                a[1] = 10;
+                a[2] += 5;
+                let mut sum = 0;
+                for x in a { sum += x; }
+                for let mut i = 0; i < 3; i += 1 { sum += i; }
+                let mut n = 0;
+                while n < 4 { n += 1; if n == 2 { continue; } sum += 100; }
+                sum + loop { break 1000; }
+            }"),
+        Ok(Value::Int(1322))
+    );
+}
+
+#[test]
+fn runtime_errors() {
+    assert_eq!(
+        run("func main() -> int { let z = 0; 1 / z }"),
+        Err(String::from("DivisionByZero"))
+    );
+    assert_eq!(
+        run("func main() -> int { let a = [1]; a[3] }"),
+        Err(String::from("IndexOutOfBounds"))
+    );
+    assert_eq!(
+        run("func main() -> int { let big = 9223372036854775807; big + 1 }"),
+        Err(String::from("IntegerOverflow"))
+    );
+}
+
+#[test]
+fn loop_example() {
+    let source = std::fs::read_to_string("examples/loop_basic.rul").unwrap();
+    assert_eq!(run(&source), Ok(Value::Int(2455)));
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use common::FILENAME;
+use regula_project::{CompilerError, Globals, TypeChecker, TypeEnv};
+
+// The first error as its kind, the text it points at and its note, if any.
+#[derive(Debug, PartialEq)]
+struct Diagnostic {
+    kind: String,
+    text: String,
+    note: Option<(String, &'static str)>,
+}
+
+fn diagnostic(source: &str, e: CompilerError) -> Diagnostic {
+    Diagnostic {
+        kind: e.kind.to_string(),
+        text: common::text(source, e.span),
+        note: e
+            .note
+            .map(|(span, note)| (common::text(source, span), note)),
+    }
+}
+
+fn check(source: &str) -> Result<(), Diagnostic> {
+    let input = common::input(source);
+    let mut globals = Globals::new();
+    let program = common::resolve(&input, &mut globals).map_err(|e| diagnostic(source, e))?;
+    let mut types = TypeEnv::new();
+    TypeChecker::new(&mut types, &input, FILENAME.to_string())
+        .check(&program)
+        .map(|_| ())
+        .map_err(|e| diagnostic(source, e))
+}
+
+fn mismatch(text: &str, note: Option<(&str, &'static str)>) -> Result<(), Diagnostic> {
+    Err(Diagnostic {
+        kind: String::from("TypeMismatch"),
+        text: text.to_string(),
+        note: note.map(|(text, note)| (text.to_string(), note)),
+    })
+}
+
+#[test]
+fn annotations() {
+    assert_eq!(check("let x: int = 1; let y: [str] = [\"a\"];"), Ok(()));
+    assert_eq!(
+        check("let x: int = \"hi\";"),
+        mismatch("\"hi\"", Some(("int", "expected because of this")))
+    );
+    assert_eq!(
+        check("let x = 1; let y: float = x;"),
+        mismatch("x", Some(("float", "expected because of this")))
+    );
+    assert_eq!(
+        check("const N: bool = 3;"),
+        mismatch("3", Some(("bool", "expected because of this")))
+    );
+}
+
+#[test]
+fn inferred_bindings() {
+    assert_eq!(check("let mut x = 1; x = 2;"), Ok(()));
+    assert_eq!(check("let mut x = 1; x = 2.0;"), mismatch("2.0", None));
+    assert_eq!(check("let a = [1, true];"), mismatch("true", None));
+}
+
+#[test]
+fn operands() {
+    assert_eq!(check("let a = 1 + 2 * 3 << 1 & 7;"), Ok(()));
+    assert_eq!(
+        check("let a = 1 + 2.0;"),
+        Err(Diagnostic {
+            kind: String::from("BinaryOperandTypes"),
+            text: String::from("1 + 2.0"),
+            note: None,
+        })
+    );
+    assert_eq!(
+        check("let a = !1;"),
+        Err(Diagnostic {
+            kind: String::from("UnaryOperandType"),
+            text: String::from("!1"),
+            note: None,
+        })
+    );
+    assert_eq!(check("let a = 1; if a { }"), mismatch("a", None));
+}
+
+#[test]
+fn functions() {
+    assert_eq!(
+        check("func f(a: int) -> int { a } let b: int = f(1);"),
+        Ok(())
+    );
+    assert_eq!(
+        check("func f(a: int) -> int { a } f(\"x\");"),
+        mismatch("\"x\"", None)
+    );
+    assert_eq!(
+        check("func f() -> int { \"x\" }"),
+        mismatch("{ \"x\" }", Some(("int", "expected because of this")))
+    );
+    assert_eq!(
+        check("func f() -> int { return true; }"),
+        Err(Diagnostic {
+            kind: String::from("TypeMismatch"),
+            text: String::from("true"),
+            note: Some((String::from("int"), "expected because of this")),
+        })
+    );
+}
+
+#[test]
+fn missing_else() {
+    assert_eq!(check("let a: int = if true { 1 } else { 2 };"), Ok(()));
+    assert_eq!(check("if true { 1; }"), Ok(()));
+    assert_eq!(
+        check("let a = if true { 1 };"),
+        Err(Diagnostic {
+            kind: String::from("MissingElse"),
+            text: String::from("if true { 1 }"),
+            note: None,
+        })
+    );
+    assert_eq!(
+        check("let a = if true { 1 } else { \"x\" };"),
+        mismatch("{ \"x\" }", Some(("{ 1 }", "expected because of this")))
+    );
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use regula_project::ast::{Ast, BinOp, Expr, Literal, NodeId, Param, Program};
+use regula_project::visit::{each_child, nth_child, walk_expr, walk_expr_mut, walk_fold};
+use regula_project::{Fold, Symbol, Visitor, VisitorMut};
+
+fn parse(source: &str) -> Program {
+    common::parse(&common::input(source)).unwrap()
+}
+
+#[derive(Default)]
+struct Names(Vec<String>);
+
+impl Visitor for Names {
+    fn visit_expr(&mut self, ast: &Ast, id: NodeId) {
+        if let Expr::Ident { name, .. } = &ast[id] {
+            self.0.push(name.to_string());
+        }
+        walk_expr(self, ast, id);
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        self.0.push(format!("param {}", param.name));
+    }
+}
+
+#[test]
+fn visits_every_node_in_source_order() {
+    let program = parse(
+        "func f(a: int, b: int) -> int {
+            let mut c = [a, b];
+            for let mut i = x; i < y; i += 1 { c[i] = g(i); }
+            if c[0] > z { return w; } else { loop { break v; } }
+            f\"{u}\"
+        }",
+    );
+    let mut names = Names::default();
+    names.visit_expr(&program.ast, program.body);
+    assert_eq!(
+        names.0,
+        [
+            "param a", "param b", "c", "a", "b", "i", "x", "i", "y", "i", "c", "i", "g", "i", "c",
+            "z", "w", "v", "u",
+        ]
+    );
+}
+
+struct Rename(Symbol, Symbol);
+
+impl VisitorMut for Rename {
+    fn visit_expr_mut(&mut self, ast: &mut Ast, id: NodeId) {
+        if let Expr::Ident { name, .. } = &mut ast[id]
+            && *name == self.0
+        {
+            *name = self.1;
+        }
+        walk_expr_mut(self, ast, id);
+    }
+
+    fn visit_param_mut(&mut self, param: &mut Param) {
+        if param.name == self.0 {
+            param.name = self.1;
+        }
+    }
+}
+
+#[test]
+fn renames_in_place() {
+    let mut program = parse("func f(old: int) -> int { old + other(old) }");
+    Rename(Symbol::intern("old"), Symbol::intern("new"))
+        .visit_expr_mut(&mut program.ast, program.body);
+
+    let mut names = Names::default();
+    names.visit_expr(&program.ast, program.body);
+    assert_eq!(names.0, ["param new", "new", "other", "new"]);
+}
+
+// Folds additions of integer literals, bottom up.
+struct ConstantFold;
+
+impl Fold for ConstantFold {
+    fn fold_expr(&mut self, ast: &mut Ast, id: NodeId) -> NodeId {
+        let id = walk_fold(self, ast, id);
+        if let Expr::Binary {
+            op: BinOp::Add,
+            left,
+            right,
+            span,
+        } = &ast[id]
+            && let Expr::Literal {
+                lit: Literal::Int(a),
+                ..
+            } = &ast[*left]
+            && let Expr::Literal {
+                lit: Literal::Int(b),
+                ..
+            } = &ast[*right]
+        {
+            let lit = Literal::Int(a + b);
+            let span = *span;
+            return ast.alloc(Expr::Literal { lit, span });
+        }
+        id
+    }
+}
+
+#[test]
+fn folds_into_new_nodes() {
+    let mut program = parse("let a = 1 + 2 + 3; a + 4;");
+    let body = ConstantFold.fold_expr(&mut program.ast, program.body);
+
+    let Expr::Block { exprs, .. } = &program.ast[body] else {
+        panic!("expected a block");
+    };
+    let Expr::Let {
+        init: Some(init), ..
+    } = &program.ast[exprs[0]]
+    else {
+        panic!("expected a let");
+    };
+    assert!(matches!(
+        program.ast[*init],
+        Expr::Literal {
+            lit: Literal::Int(6),
+            ..
+        }
+    ));
+    assert!(matches!(program.ast[exprs[1]], Expr::Binary { .. }));
+}
+
+// Every node lists the same children whether walked in one go or by index.
+struct Agree;
+
+impl Visitor for Agree {
+    fn visit_expr(&mut self, ast: &Ast, id: NodeId) {
+        let mut children = Vec::new();
+        each_child(&ast[id], |child| children.push(child));
+        let by_index: Vec<_> = (0..)
+            .map_while(|index| nth_child(&ast[id], index))
+            .collect();
+        assert_eq!(children, by_index, "{:?}", ast[id]);
+        walk_expr(self, ast, id);
+    }
+}
+
+#[test]
+fn indexed_children_match() {
+    let program = parse(
+        "func f(a: int) -> int {
+            let mut c = [a, 2];
+            for let mut i = 0; i < 2; i += 1 { c[i] = f(i); }
+            for let mut j = 0;; { break; }
+            for x in c { if x > 1 { return x; } }
+            while false { continue; }
+            const N: int = -1;
+            f\"{N}\"; loop { break 1; }
+        }",
+    );
+    Agree.visit_expr(&program.ast, program.body);
+}